@@ -1,2 +1,359 @@
 //This file will contain functions to access the configuration
 //
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Weekday};
+use home::home_dir;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::data::Range;
+
+fn timewarrior_path() -> Result<PathBuf> {
+    let mut path = home_dir().context("Cannot determine the home directory")?;
+    path.push(".timewarrior");
+
+    Ok(path)
+}
+
+/// Return the path of the timewarrior data directory: `${HOME}/.timewarrior/data`
+pub fn data_path() -> Result<PathBuf> {
+    Ok(timewarrior_path()?.join("data"))
+}
+
+/// Return the path of the timewarrior configuration file: `${HOME}/.timewarrior/timewarrior.cfg`
+pub fn config_path() -> Result<PathBuf> {
+    Ok(timewarrior_path()?.join("timewarrior.cfg"))
+}
+
+/// Represent the timewarrior configuration, as a list of keys and values.
+///
+/// Hierarchical definitions are flattened, so that
+///
+/// ```text
+/// define exclusions:
+///   monday = <8:00 >18:00
+/// ```
+///
+/// gives the key `exclusions.monday`.
+#[derive(Clone, Default, Debug)]
+pub struct Config {
+    values: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Load the configuration file at path. A missing file gives an empty configuration.
+    pub fn load(path: &Path) -> Result<Config> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Return the value of the given key, if set.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// Return the exclusions defined in the configuration.
+    pub fn exclusions(&self) -> Result<Exclusions> {
+        let mut exclusions = Exclusions::default();
+        for (day, name) in DAYS.iter().enumerate() {
+            if let Some(value) = self.get(&format!("exclusions.{}", name)) {
+                exclusions.days[day] = parse_exclusions(value)?;
+            }
+        }
+
+        Ok(exclusions)
+    }
+
+    /// Return the first day of the week, from the `weekstart` key. Weeks start on Monday if it is
+    /// not set.
+    pub fn weekstart(&self) -> Result<Weekday> {
+        match self.get("weekstart") {
+            Some(day) => match day.parse() {
+                Ok(weekday) => Ok(weekday),
+                Err(_) => bail!("Invalid weekstart '{}'", day),
+            },
+            None => Ok(Weekday::Mon),
+        }
+    }
+
+    /// Return the tags that have settings, such as a description or a colour, in the `define tags`
+    /// section of the configuration.
+    pub fn tags(&self) -> BTreeSet<String> {
+        self.values
+            .range("tags.".to_string()..)
+            .take_while(|(key, _)| key.starts_with("tags."))
+            .filter_map(|(key, _)| Some(key["tags.".len()..].rsplit_once('.')?.0.to_string()))
+            .collect()
+    }
+
+    /// Return the holidays defined in the configuration, by day. Holidays are defined as in timew,
+    /// by locale:
+    ///
+    /// ```text
+    /// define holidays:
+    ///   en-US:
+    ///     2022_07_04 = Independence Day
+    /// ```
+    pub fn holidays(&self) -> Result<BTreeMap<NaiveDate, String>> {
+        let mut holidays = BTreeMap::new();
+        for (key, name) in self.values.range("holidays.".to_string()..) {
+            if !key.starts_with("holidays.") {
+                break;
+            }
+            let day = key.rsplit('.').next().unwrap_or_default();
+            let day = NaiveDate::parse_from_str(day, "%Y_%m_%d")
+                .or_else(|_| NaiveDate::parse_from_str(day, "%Y-%m-%d"))
+                .with_context(|| format!("Invalid holiday date '{}'", day))?;
+            holidays.insert(day, name.clone());
+        }
+
+        Ok(holidays)
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut values = BTreeMap::new();
+        // The sections the current line is in, with their indentation
+        let mut sections: Vec<(usize, String)> = vec![];
+
+        for line in s.lines() {
+            let line = strip_comment(line);
+            if line.trim().is_empty() || line.trim_start().starts_with("import ") {
+                continue;
+            }
+
+            let indent = line.len() - line.trim_start().len();
+            while sections.last().is_some_and(|(i, _)| *i >= indent) {
+                sections.pop();
+            }
+
+            let line = line.trim();
+            if let Some((name, value)) = line.split_once('=') {
+                let mut key: Vec<&str> = sections.iter().map(|(_, s)| s.as_str()).collect();
+                key.push(key_name(name));
+                values.insert(key.join("."), value.trim().trim_matches('"').to_string());
+            } else if let Some(section) = line.strip_suffix(':') {
+                let section = section.strip_prefix("define ").unwrap_or(section);
+                sections.push((indent, key_name(section).to_string()));
+            }
+            // Other lines, such as the settings of extensions, are skipped.
+        }
+
+        Ok(Config { values })
+    }
+}
+
+// Remove the comment at the end of the line: a `#` starts a comment when it is outside quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn key_name(key: &str) -> &str {
+    key.trim().trim_matches('"')
+}
+
+const DAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+// An excluded time of the day. The end is `None` for an exclusion lasting until midnight.
+type DayExclusion = (NaiveTime, Option<NaiveTime>);
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .with_context(|| format!("Cannot parse exclusion time \"{}\"", time))
+}
+
+/*
+Parse the exclusions of a day. They are a list of:
+ <HH:MM   : excluded from midnight to HH:MM
+ >HH:MM   : excluded from HH:MM to midnight
+ HH:MM-HH:MM : excluded between the 2 times
+ */
+fn parse_exclusions(value: &str) -> Result<Vec<DayExclusion>> {
+    value
+        .split_whitespace()
+        .map(|e| {
+            if let Some(t) = e.strip_prefix('<') {
+                Ok((NaiveTime::MIN, Some(parse_time(t)?)))
+            } else if let Some(t) = e.strip_prefix('>') {
+                Ok((parse_time(t)?, None))
+            } else if let Some((from, to)) = e.split_once('-') {
+                Ok((parse_time(from)?, Some(parse_time(to)?)))
+            } else {
+                bail!("Cannot parse exclusion \"{}\"", e)
+            }
+        })
+        .collect()
+}
+
+/// Represent the exclusions of each day of the week: the times that are not supposed to be
+/// tracked, in local time.
+#[derive(Clone, Default, Debug)]
+pub struct Exclusions {
+    // Exclusions of each day of the week, starting on Monday
+    days: [Vec<DayExclusion>; 7],
+}
+
+impl Exclusions {
+    /// Return the excluded time in the given Range. An open Range ends now.
+    pub fn ranges(&self, range: &Range) -> Vec<Range> {
+        let to = range.to().unwrap_or_else(chrono::Utc::now);
+        let mut ranges = vec![];
+        let mut day = range.from().with_timezone(&Local).date_naive();
+        let last = to.with_timezone(&Local).date_naive();
+
+        while day <= last {
+            for (from, end) in &self.days[day.weekday().num_days_from_monday() as usize] {
+                let from = Local.from_local_datetime(&day.and_time(*from)).earliest();
+                let end = match end {
+                    Some(e) => Local.from_local_datetime(&day.and_time(*e)).earliest(),
+                    None => Local
+                        .from_local_datetime(&(day + Duration::days(1)).and_time(NaiveTime::MIN))
+                        .earliest(),
+                };
+
+                if let (Some(from), Some(end)) = (from, end) {
+                    let excluded = Range::new(from.into(), Some(end.into()));
+                    if let Some(r) = excluded.ok().and_then(|e| e.intersection(range)) {
+                        ranges.push(r);
+                    }
+                }
+            }
+            day += Duration::days(1);
+        }
+
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use crate::config::Config;
+    use crate::data::Range;
+    use chrono::{Duration, Local, NaiveDate, TimeZone, Weekday};
+
+    #[test]
+    fn test_config_parse() {
+        let config: Config = "# Comment\n\
+                              weekstart = sunday\n\
+                              reports.day.hours = auto # comment\n\
+                              define exclusions:\n\
+                              \x20 monday = <8:00 12:00-12:45 >18:00\n\
+                              define tags:\n\
+                              \x20 \"my tag\":\n\
+                              \x20   color = \"blue\"\n\
+                              \x20 \"bug#1\": # quoted\n\
+                              \x20   description = \"see #12\" # comment\n\
+                              import /usr/share/timewarrior/themes/dark.theme\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(config.get("weekstart"), Some("sunday"));
+        assert_eq!(config.get("reports.day.hours"), Some("auto"));
+        assert_eq!(
+            config.get("exclusions.monday"),
+            Some("<8:00 12:00-12:45 >18:00")
+        );
+        assert_eq!(config.get("tags.my tag.color"), Some("blue"));
+        assert_eq!(config.get("tags.bug#1.description"), Some("see #12"));
+        assert_eq!(config.get("unknown"), None);
+        assert_eq!(
+            config.tags().into_iter().collect::<Vec<String>>(),
+            vec!["bug#1", "my tag"]
+        );
+        assert_eq!(config.weekstart().unwrap(), Weekday::Sun);
+        assert_eq!(Config::default().weekstart().unwrap(), Weekday::Mon);
+        assert!(config.holidays().unwrap().is_empty());
+
+        let config: Config = "define holidays:\n\
+                              \x20 en-US:\n\
+                              \x20   2022_07_04 = Independence Day\n\
+                              \x20 fr-FR:\n\
+                              \x20   2022-07-14 = \"Fête nationale\"\n"
+            .parse()
+            .unwrap();
+        let holidays: Vec<(NaiveDate, String)> = config.holidays().unwrap().into_iter().collect();
+        assert_eq!(
+            holidays,
+            vec![
+                (
+                    NaiveDate::from_ymd_opt(2022, 7, 4).unwrap(),
+                    "Independence Day".to_string()
+                ),
+                (
+                    NaiveDate::from_ymd_opt(2022, 7, 14).unwrap(),
+                    "Fête nationale".to_string()
+                ),
+            ]
+        );
+        let config: Config = "holidays.en-US.july = Holiday".parse().unwrap();
+        assert!(config.holidays().is_err());
+
+        let config: Config = "weekstart = someday".parse().unwrap();
+        assert!(config.weekstart().is_err());
+
+        let config: Config = "not a config line\nweekstart = monday".parse().unwrap();
+        assert_eq!(config.get("weekstart"), Some("monday"));
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let config: Config = "exclusions.monday = <8:00 12:00-12:45 >18:00\n\
+                              exclusions.tuesday = 10:00:00-11:00"
+            .parse()
+            .unwrap();
+        let exclusions = config.exclusions().unwrap();
+
+        // From Monday 11 July 2022 to Tuesday 12 July 2022 at noon, in local time
+        let range = Range::new(
+            Local.with_ymd_and_hms(2022, 7, 11, 0, 0, 0).unwrap().into(),
+            Some(
+                Local
+                    .with_ymd_and_hms(2022, 7, 12, 12, 0, 0)
+                    .unwrap()
+                    .into(),
+            ),
+        )
+        .unwrap();
+        let ranges = exclusions.ranges(&range);
+        let durations: Vec<Duration> = ranges.iter().map(|r| r.duration()).collect();
+        assert_eq!(
+            durations,
+            vec![
+                Duration::hours(8),
+                Duration::minutes(45),
+                Duration::hours(6),
+                Duration::hours(1)
+            ]
+        );
+
+        assert!("exclusions.monday = 8:00"
+            .parse::<Config>()
+            .unwrap()
+            .exclusions()
+            .is_err());
+    }
+}
@@ -1,70 +1,136 @@
-use anyhow::{bail, ensure, Result};
+use anyhow::{bail, ensure, Context, Result};
 use chrono::{
-    DateTime, Datelike, Duration, Local, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Utc,
-    Weekday,
+    DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Utc, Weekday,
 };
 use regex::Regex;
-use std::cmp::Ordering;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
 use std::fmt::{Display, Formatter};
+use std::fs;
 use std::fs::{read_dir, File};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io;
-use std::io::BufRead;
+use std::io::Write;
 use std::ops::Add;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::SystemTime;
 
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_while1};
-use nom::character::complete::{alphanumeric0, char as nom_char, char};
-use nom::combinator::{map, map_opt, map_res};
-use nom::multi::separated_list0;
-use nom::sequence::{delimited, preceded, separated_pair};
+use nom::bytes::complete::{escaped, tag, take_while1};
+use nom::character::complete::{anychar, char as nom_char, multispace0, none_of, u64};
+use nom::combinator::{map, map_opt, map_res, opt, verify};
+use nom::multi::{many0, separated_list0};
+use nom::sequence::{delimited, preceded, separated_pair, tuple};
 use nom::IResult as NomResult;
 
-fn parse_tags(text: &str) -> NomResult<&str, Vec<&str>> {
-    let sep = ' ';
-    let quote = '"';
-    let x = separated_list0(
-        nom_char(sep),
-        alt((
-            delimited(
-                nom_char(quote),
-                take_while1(|c| c != quote),
-                nom_char(quote),
-            ),
-            take_while1(|c| c != quote && c != sep),
-        )),
-    )(text);
+use crate::date::{last_weekday, local_date_time, period_start, WEEKDAYS};
+use crate::duration::{format_duration, DurationFormat};
+use crate::filter::Filter;
+use crate::rangeset::RangeSet;
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(n)) if n == '\\' || n == '"' => {
+                out.push(n);
+                chars.next();
+            }
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some('r')) => {
+                out.push('\r');
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+// Line breaks are escaped too, as each entry must stay on its own line of the data file.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+// Escape the text for a JSON string: all the control characters must be escaped, not only the
+// line breaks.
+fn escape_json(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
 
-    x
+fn parse_quoted(input: &str) -> NomResult<&str, String> {
+    delimited(
+        nom_char('"'),
+        map(opt(escaped(none_of("\\\""), '\\', anychar)), |s| {
+            unescape(s.unwrap_or_default())
+        }),
+        nom_char('"'),
+    )(input)
+}
+
+// A lone '#' separates the tags from the annotation, it is never a tag.
+fn parse_tag(input: &str) -> NomResult<&str, String> {
+    alt((
+        parse_quoted,
+        map(
+            verify(take_while1(|c| c != '"' && c != ' '), |s: &str| s != "#"),
+            String::from,
+        ),
+    ))(input)
+}
+
+fn parse_tags(text: &str) -> NomResult<&str, Vec<String>> {
+    many0(preceded(nom_char(' '), parse_tag))(text)
+}
+
+// Tags are quoted the same way timew does it, so that rewritten lines stay identical.
+fn quote_tag(tag: &str) -> String {
+    if tag.is_empty() || tag.contains(|c: char| c.is_control() || " \"#+-/()<^!=~_%".contains(c)) {
+        format!("\"{}\"", escape(tag))
+    } else {
+        tag.to_string()
+    }
 }
 
-fn parse_date(input: &str) -> NomResult<&str, DateTime<Utc>> {
+pub(crate) fn parse_date(input: &str) -> NomResult<&str, DateTime<Utc>> {
     map_opt(take_while1(|c| c != ' '), |v: &str| {
-        let parsed = NaiveDateTime::parse_from_str(v.trim(), "%Y%m%dT%H%M%SZ");
-        return if let Ok(d) = parsed {
-            Some(DateTime::<Utc>::from_utc(d, Utc))
-        } else {
-            None
-        };
+        NaiveDateTime::parse_from_str(v.trim(), "%Y%m%dT%H%M%SZ")
+            .ok()
+            .map(|d| Utc.from_utc_datetime(&d))
     })(input)
 }
 
 /*
 Parse a range.
-Ranges can have multiple formats:
+Ranges can have two formats:
  <datetime> - <datetime>
  <datetime>
- :<period>
-where:
-  datetime is in the format "%Y%m%dT%H%M%SZ" (e.g.: 20220711T133312Z)
-  period is one of
-   - today
-   - yesterday
-   - week
-   - lastweek
-   - month
-   - lastmonth
+where datetime is in the format "%Y%m%dT%H%M%SZ" (e.g.: 20220711T133312Z). Periods such as `:week`
+are given to `Range::from_period`, as they depend on the first day of the week.
  */
 fn parse_range(input: &str) -> NomResult<&str, Range> {
     alt((
@@ -73,26 +139,46 @@ fn parse_range(input: &str) -> NomResult<&str, Range> {
             |(from, to)| Range::new(from, Some(to)),
         ),
         map_res(parse_date, |r| Range::new(r, None)),
-        preceded(char(':'), map_res(alphanumeric0, Range::from_period_str)),
     ))(input)
 }
 
+/*
+Parse an entry of a data file.
+Entries have the format:
+ inc <range>[ # <tags>][ # "<annotation>"]
+When there is an annotation but no tags, the tags are left empty: `inc <range> # # "<annotation>"`
+ */
 fn parse_entry(input: &str) -> NomResult<&str, TimeEntry> {
-    preceded(
-        tag("inc "),
-        map(separated_pair(parse_range, tag(" # "), parse_tags), |t| {
-            TimeEntry {
-                range: t.0,
-                tags: t
-                    .1
-                    .into_iter()
-                    .map(|s| s.to_string().clone())
-                    .collect::<Vec<String>>(),
-                id: 0,
-            }
-        }),
+    map(
+        tuple((
+            preceded(tag("inc "), parse_range),
+            opt(preceded(tag(" #"), parse_tags)),
+            opt(preceded(tag(" # "), parse_quoted)),
+        )),
+        |(range, tags, annotation)| TimeEntry {
+            range,
+            tags: tags.unwrap_or_default(),
+            annotation,
+            id: 0,
+            location: None,
+        },
     )(input)
 }
+
+// Return the start of the day in local time, as `Range::day_in` does.
+pub(crate) fn local_midnight(date: NaiveDate) -> Result<DateTime<Local>> {
+    local_date_time(&Local, date.and_time(NaiveTime::MIN))
+}
+
+// Return the first day of the month before the one of the given day.
+fn previous_month(date: NaiveDate) -> NaiveDate {
+    match date.month() {
+        1 => NaiveDate::from_ymd_opt(date.year() - 1, 12, 1),
+        m => NaiveDate::from_ymd_opt(date.year(), m - 1, 1),
+    }
+    .unwrap()
+}
+
 /// Specify an optionally opened range of time. Times are stored in UTC and expected to be given in
 /// UTC time.
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -102,26 +188,70 @@ pub struct Range {
 }
 
 impl Range {
-    fn from_period_str(period: &str) -> Result<Range> {
+    /// Create a Range from a period name of timew, such as `week` or `lastquarter`, as in the
+    /// `:week` hint, in local time. Weeks start on `weekstart`.
+    pub fn from_period(period: &str, weekstart: Weekday) -> Result<Range> {
+        Range::from_period_in(period, Local::now().date_naive(), weekstart, &Local)
+    }
+
+    /// Create a Range from a period name of timew, for the given day in the given timezone. Weeks
+    /// start on `weekstart`.
+    ///
+    /// Periods are `today`, `yesterday`, `week`, `lastweek`, `fortnight` (last week and this
+    /// week), `month`, `lastmonth`, `quarter`, `lastquarter`, `year`, `lastyear`, `monday` to
+    /// `sunday` (the last such day, today included) and `all`.
+    pub fn from_period_in<Tz: TimeZone>(
+        period: &str,
+        today: NaiveDate,
+        weekstart: Weekday,
+        tz: &Tz,
+    ) -> Result<Range> {
+        // The Range from the start of the period of the given kind that is `first` periods after
+        // the one of today to the start of the period that is `end` periods after it.
+        let periods = |kind: char, first: i32, end: i32| match (
+            period_start(today, kind, first, weekstart),
+            period_start(today, kind, end, weekstart),
+        ) {
+            (Some(first), Some(end)) => Range::days_in(first, end, tz),
+            _ => bail!("Cannot determine the periods around {}", today),
+        };
+
         match period {
-            "today" => Range::today(),
-            "yesterday" => Range::yesterday(),
-            "week" => Range::current_week(),
-            "lastweek" => Range::last_week(),
-            "month" => Range::current_month(),
-            "lastmonth" => Range::last_month(),
-            _ => bail!(""),
+            "today" => Range::day_in(today, tz),
+            "yesterday" => Range::day_in(today - Duration::days(1), tz),
+            "week" => Range::week_starting_in(today, weekstart, tz),
+            "lastweek" => Range::week_starting_in(today - Duration::weeks(1), weekstart, tz),
+            "month" => Range::month_in(today, tz),
+            "lastmonth" => Range::month_in(previous_month(today), tz),
+            "fortnight" => periods('w', -1, 1),
+            "quarter" => periods('q', 0, 1),
+            "lastquarter" => periods('q', -1, 0),
+            "year" => periods('y', 0, 1),
+            "lastyear" => periods('y', -1, 0),
+            "all" => Range::all(),
+            _ => match WEEKDAYS.iter().find(|(name, _)| *name == period) {
+                Some((_, weekday)) => Range::day_in(last_weekday(today, *weekday), tz),
+                None => bail!("Unknown period '{}'", period),
+            },
         }
     }
 
-    /// Print the duration in a HH:MM:SS format
+    /// Create a Range representing the last day that was the given weekday, today included
+    pub fn weekday(weekday: Weekday) -> Result<Range> {
+        Range::day_in(last_weekday(Local::now().date_naive(), weekday), &Local)
+    }
+
+    /// Create an open Range containing all the entries of the database
+    pub fn all() -> Result<Range> {
+        Range::new(DateTime::UNIX_EPOCH, None)
+    }
+
+    /// Print the duration in a HH:MM:SS format, preceded by `-` if it is negative
     pub fn pretty_duration(d: &Duration) -> String {
-        format!(
-            "{:02}:{:02}:{:02}",
-            d.num_hours(),
-            d.num_minutes() % 60,
-            d.num_seconds() % 60
-        )
+        let clock = format_duration(d, DurationFormat::Clock);
+        // The hours have at least two digits
+        let (sign, time) = clock.split_at(usize::from(clock.starts_with('-')));
+        format!("{}{:0>8}", sign, time)
     }
 
     /// Create a new Range with the specified `from` and `to`
@@ -130,117 +260,145 @@ impl Range {
             return Ok(Range { from, to });
         }
 
-        return if from + Duration::seconds(1) <= to.unwrap() {
+        if from + Duration::seconds(1) <= to.unwrap() {
             Ok(Range { from, to })
         } else {
             bail!("Range is invalid: from cannot be greater that to");
-        };
+        }
     }
 
-    /// Create a new Range representing the day containing the given date/time
-    pub fn day(day: &DateTime<Local>) -> Result<Range> {
-        let morning = match Utc.from_local_datetime(&day.naive_utc().date().and_hms(0, 0, 0)) {
-            LocalResult::Single(t) => t,
-            _ => bail!("Cannot determine morning"),
-        };
-        let evening = match Utc.from_local_datetime(&day.naive_utc().date().and_hms(23, 59, 59)) {
-            LocalResult::Single(t) => Some(t),
-            _ => bail!("Cannot determine evening"),
+    // Create a Range from the midnight starting the day `first` to the midnight starting the day
+    // `end`, in the given timezone.
+    fn days_in<Tz: TimeZone>(first: NaiveDate, end: NaiveDate, tz: &Tz) -> Result<Range> {
+        let midnight = |date: NaiveDate| -> Result<DateTime<Utc>> {
+            Ok(local_date_time(tz, date.and_time(NaiveTime::MIN))?.with_timezone(&Utc))
         };
 
-        Range::new(morning, evening)
+        Range::new(midnight(first)?, Some(midnight(end)?))
+    }
+
+    /// Create a new Range representing the given day in the given timezone. The Range goes from
+    /// midnight to the next midnight, so it lasts 23 or 25 hours when the offset of the timezone
+    /// changes during the day. If midnight does not exist, the day starts one hour later.
+    pub fn day_in<Tz: TimeZone>(date: NaiveDate, tz: &Tz) -> Result<Range> {
+        Range::days_in(date, date + Duration::days(1), tz)
+    }
+
+    /// Create a new Range representing the day containing the given date/time, in its timezone
+    pub fn day<Tz: TimeZone>(day: &DateTime<Tz>) -> Result<Range> {
+        Range::day_in(day.date_naive(), &day.timezone())
     }
 
     /// Create a Range representing today
     pub fn today() -> Result<Range> {
-        Self::day(&Local::today().and_hms(0, 0, 0))
+        Range::day_in(Local::now().date_naive(), &Local)
     }
 
     /// Create a Range representing yesterday
     pub fn yesterday() -> Result<Range> {
-        let day = Local::today() - Duration::days(1);
-        Self::day(&day.and_hms(0, 0, 0))
+        Range::day_in(Local::now().date_naive() - Duration::days(1), &Local)
     }
 
-    /// Create a new Range representing the week containing the given date/time
-    pub fn week(day: &DateTime<Local>) -> Result<Range> {
-        let mut current = day.clone();
-        while current.weekday() != Weekday::Mon {
-            current = current - Duration::days(1);
-        }
+    /// Create a new Range representing the week that starts on `weekstart` and contains the given
+    /// day, in the given timezone
+    pub fn week_starting_in<Tz: TimeZone>(
+        date: NaiveDate,
+        weekstart: Weekday,
+        tz: &Tz,
+    ) -> Result<Range> {
+        let first = last_weekday(date, weekstart);
+        Range::days_in(first, first + Duration::weeks(1), tz)
+    }
 
-        let monday = match Utc.from_local_datetime(&current.naive_utc().date().and_hms(0, 0, 0)) {
-            LocalResult::Single(t) => t,
-            _ => bail!("Cannot determine morning"),
-        };
+    /// Create a new Range representing the week, from Monday to Sunday, containing the given day
+    /// in the given timezone
+    pub fn week_in<Tz: TimeZone>(date: NaiveDate, tz: &Tz) -> Result<Range> {
+        Range::week_starting_in(date, Weekday::Mon, tz)
+    }
 
-        let sunday = match Utc.from_local_datetime(
-            &(current + Duration::days(6))
-                .naive_utc()
-                .date()
-                .and_hms(23, 59, 59),
-        ) {
-            LocalResult::Single(t) => Some(t),
-            _ => bail!("Cannot determine morning"),
-        };
+    /// Create a new Range representing the week, from Monday to Sunday, containing the given
+    /// date/time, in its timezone
+    pub fn week<Tz: TimeZone>(day: &DateTime<Tz>) -> Result<Range> {
+        Range::week_in(day.date_naive(), &day.timezone())
+    }
 
-        Range::new(monday, sunday)
+    /// Create a Range representing the current week, starting on `weekstart`
+    pub fn current_week(weekstart: Weekday) -> Result<Range> {
+        Range::week_starting_in(Local::now().date_naive(), weekstart, &Local)
     }
 
-    /// Create a Range representing the current week
-    pub fn current_week() -> Result<Range> {
-        Self::week(&Local::today().and_hms(0, 0, 0))
+    /// Create a Range representing last week, starting on `weekstart`
+    pub fn last_week(weekstart: Weekday) -> Result<Range> {
+        let day = Local::now().date_naive() - Duration::weeks(1);
+        Range::week_starting_in(day, weekstart, &Local)
     }
 
-    /// Create a Range representing last week
-    pub fn last_week() -> Result<Range> {
-        let day = Local::today() - Duration::days(7);
-        Self::week(&day.and_hms(0, 0, 0))
+    /// Create a new Range representing the month containing the given day in the given timezone
+    pub fn month_in<Tz: TimeZone>(date: NaiveDate, tz: &Tz) -> Result<Range> {
+        let first = date - Duration::days((date.day() - 1).into());
+        match first.checked_add_months(Months::new(1)) {
+            Some(end) => Range::days_in(first, end, tz),
+            None => bail!("Cannot determine the end of the month of {}", date),
+        }
     }
 
-    /// Create a new Range representing the month containing the given date/time
-    pub fn month(day: &DateTime<Local>) -> Result<Range> {
-        let mut current = day.clone();
-        while current.day() != 1 {
-            current = current - Duration::days(1);
+    /// Create a new Range representing the given month of the given year, in the given timezone.
+    /// Months are numbered from 1 to 12.
+    pub fn year_month_in<Tz: TimeZone>(year: i32, month: u32, tz: &Tz) -> Result<Range> {
+        match NaiveDate::from_ymd_opt(year, month, 1) {
+            Some(first) => Range::month_in(first, tz),
+            None => bail!("Month {}-{} does not exist", year, month),
         }
+    }
 
-        let first = match Utc.from_local_datetime(&current.naive_utc().date().and_hms(0, 0, 0)) {
-            LocalResult::Single(t) => t,
-            _ => bail!("Cannot determine morning"),
-        };
+    /// Create a new Range representing the given month of the given year, in local time
+    pub fn year_month(year: i32, month: u32) -> Result<Range> {
+        Range::year_month_in(year, month, &Local)
+    }
 
-        current = current + Duration::days(26);
-        while current.day() != 1 {
-            current = current + Duration::days(1);
+    /// Create a new Range representing the given ISO 8601 week of the given ISO year, in the given
+    /// timezone. ISO weeks start on Monday, and the first week of a year is the one containing its
+    /// first Thursday, so some years have 53 weeks.
+    pub fn iso_week_in<Tz: TimeZone>(year: i32, week: u32, tz: &Tz) -> Result<Range> {
+        match NaiveDate::from_isoywd_opt(year, week, Weekday::Mon) {
+            Some(monday) => Range::days_in(monday, monday + Duration::weeks(1), tz),
+            None => bail!("Week {} of {} does not exist", week, year),
         }
+    }
 
-        let last = match Utc.from_local_datetime(
-            &(current - Duration::days(1))
-                .naive_utc()
-                .date()
-                .and_hms(23, 59, 59),
-        ) {
-            LocalResult::Single(t) => Some(t),
-            _ => bail!("Cannot determine morning"),
-        };
+    /// Create a new Range representing the given ISO 8601 week of the given ISO year, in local time
+    pub fn iso_week(year: i32, week: u32) -> Result<Range> {
+        Range::iso_week_in(year, week, &Local)
+    }
 
-        Range::new(first, last)
+    /// Create a new Range representing the month containing the given date/time, in its timezone
+    pub fn month<Tz: TimeZone>(day: &DateTime<Tz>) -> Result<Range> {
+        Range::month_in(day.date_naive(), &day.timezone())
     }
 
     /// Create a Range representing the current month
     pub fn current_month() -> Result<Range> {
-        Self::month(&Local::today().and_hms(0, 0, 0))
+        Range::month_in(Local::now().date_naive(), &Local)
     }
 
     /// Create a Range representing the last month
     pub fn last_month() -> Result<Range> {
-        let mut current = Local::today();
-        let this_month = current.month();
-        while current.month() != this_month - 1 {
-            current = current - Duration::days(15);
-        }
-        Self::month(&current.and_hms(0, 0, 0))
+        Range::month_in(previous_month(Local::now().date_naive()), &Local)
+    }
+
+    /// Return the ISO 8601 week number of the local day the Range starts in
+    pub fn iso_week_number(&self) -> u32 {
+        self.from.with_timezone(&Local).iso_week().week()
+    }
+
+    /// Return the start of the Range
+    pub fn from(&self) -> DateTime<Utc> {
+        self.from
+    }
+
+    /// Return the end of the Range, if it is closed
+    pub fn to(&self) -> Option<DateTime<Utc>> {
+        self.to
     }
 
     /// Return true if the range is open. An open range is a Range that has no end set.
@@ -256,10 +414,7 @@ impl Range {
             } else {
                 self.from
             };
-            return match Range::new(from, None) {
-                Ok(r) => Some(r),
-                Err(_) => None,
-            };
+            return Range::new(from, None).ok();
         }
 
         let from_a = self.from;
@@ -278,10 +433,7 @@ impl Range {
             } else {
                 self.from
             };
-            return match Range::new(from, to_b) {
-                Ok(r) => Some(r),
-                Err(_) => None,
-            };
+            return Range::new(from, to_b).ok();
         }
 
         if let Some(to) = to_b {
@@ -294,10 +446,7 @@ impl Range {
             } else {
                 self.from
             };
-            return match Range::new(from, to_a) {
-                Ok(r) => Some(r),
-                Err(_) => None,
-            };
+            return Range::new(from, to_a).ok();
         }
 
         if let Some(to) = to_b {
@@ -313,10 +462,7 @@ impl Range {
             to_a
         };
 
-        match Range::new(from, to) {
-            Ok(r) => Some(r),
-            Err(_) => None,
-        }
+        Range::new(from, to).ok()
     }
 
     /// Return the duration of a Range
@@ -385,7 +531,7 @@ impl FromStr for Range {
             Err(_) => bail!("Cannot parse range {}", s),
         };
 
-        if let None = range.to {
+        if range.to.is_none() {
             ensure!(
                 Utc::now() - range.from >= Duration::seconds(1),
                 "From must be less than \"now - 1s\""
@@ -419,16 +565,74 @@ impl Display for Range {
     }
 }
 
-/// Represent a time entry in timewarrior. It stores the time Range, the tags and the id of the
-/// entry.
-#[derive(Clone)]
+/// Return the data files of the given timewarrior database at data_path, sorted by name.
+pub(crate) fn data_files(data_path: &Path) -> Result<Vec<PathBuf>> {
+    let file_re = Regex::new(r"^(?P<y>\d{4})-(?P<m>\d{2}).data$").unwrap();
+    let mut files = vec![];
+
+    for file in read_dir(data_path)? {
+        let file = file?;
+        if file_re.is_match(&file.file_name().to_string_lossy()) {
+            files.push(file.path());
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Location of an entry in the database: a data file and a line number, starting at 1.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Location {
+    file: PathBuf,
+    line: usize,
+}
+
+impl Location {
+    pub(crate) fn new(file: PathBuf, line: usize) -> Location {
+        Location { file, line }
+    }
+
+    /// Return the path of the data file.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Return the line number in the data file, starting at 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
+/// Represent a time entry in timewarrior. It stores the time Range, the tags, the annotation and
+/// the id of the entry.
+#[derive(Clone, PartialEq, Debug)]
 pub struct TimeEntry {
     range: Range,
     tags: Vec<String>,
+    annotation: Option<String>,
     id: usize,
+    location: Option<Location>,
 }
 
 impl TimeEntry {
+    /// Create a new entry. The entry has no ID until it is added to a Work.
+    pub fn new(range: Range, tags: Vec<String>, annotation: Option<String>) -> TimeEntry {
+        TimeEntry {
+            range,
+            tags,
+            annotation,
+            id: 0,
+            location: None,
+        }
+    }
+
     /// Return the time Range of the entry. It can be open if the entry is currently being logged.
     pub fn range(&self) -> &Range {
         &self.range
@@ -439,9 +643,20 @@ impl TimeEntry {
         &self.tags
     }
 
+    /// Return the annotation of the entry, if any.
+    pub fn annotation(&self) -> Option<&str> {
+        self.annotation.as_deref()
+    }
+
     /// Return the day of this entry. Note that this is the day of the start of the entry.
     pub fn day(&self) -> NaiveDate {
-        self.range.from.naive_local().date()
+        self.range.from.with_timezone(&Local).date_naive()
+    }
+
+    /// Return the ISO 8601 week number of the local day the entry starts in, as shown in the `Wk`
+    /// column of `timew summary`
+    pub fn iso_week_number(&self) -> u32 {
+        self.range.iso_week_number()
     }
 
     /// Return the ID of the entry. IDs are not fixed for a specific entry. IDs are always counting
@@ -449,6 +664,80 @@ impl TimeEntry {
     pub fn id(&self) -> usize {
         self.id
     }
+
+    /// Return where the entry was loaded from. Entries that were created or modified since they
+    /// were loaded have no location.
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+
+    /// Return the name of the data file the entry belongs to: the month of its start in UTC, like
+    /// timew, in the `YYYY-MM.data` format. It does not depend on the local timezone.
+    pub fn data_file_name(&self) -> String {
+        format!(
+            "{:04}-{:02}.data",
+            self.range.from.year(),
+            self.range.from.month()
+        )
+    }
+
+    // Return the entry in the JSON format of the timew undo journal.
+    fn json(&self) -> String {
+        let mut json = format!(
+            "{{\"start\":\"{}\"",
+            self.range.from.format("%Y%m%dT%H%M%SZ")
+        );
+        if let Some(to) = self.range.to {
+            json.push_str(&format!(",\"end\":\"{}\"", to.format("%Y%m%dT%H%M%SZ")));
+        }
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self
+                .tags
+                .iter()
+                .map(|t| format!("\"{}\"", escape_json(t)))
+                .collect();
+            json.push_str(&format!(",\"tags\":[{}]", tags.join(",")));
+        }
+        if let Some(annotation) = &self.annotation {
+            json.push_str(&format!(",\"annotation\":\"{}\"", escape_json(annotation)));
+        }
+        json.push('}');
+
+        json
+    }
+
+    /// Return the entry formatted as a line of a timewarrior data file.
+    pub fn serialize(&self) -> String {
+        let mut line = format!("inc {}", self.range.from.format("%Y%m%dT%H%M%SZ"));
+        if let Some(to) = self.range.to {
+            line.push_str(&format!(" - {}", to.format("%Y%m%dT%H%M%SZ")));
+        }
+
+        if !self.tags.is_empty() {
+            line.push_str(" #");
+            for tag in &self.tags {
+                line.push(' ');
+                line.push_str(&quote_tag(tag));
+            }
+        }
+
+        if let Some(annotation) = &self.annotation {
+            if self.tags.is_empty() {
+                line.push_str(" #");
+            }
+            line.push_str(&format!(" # \"{}\"", escape(annotation)));
+        }
+
+        line
+    }
+}
+
+/// Parse a line of a data file. Unlike `TimeEntry::from_str`, the whole line must be parsed.
+pub(crate) fn parse_line(line: &str) -> Result<TimeEntry> {
+    match parse_entry(line) {
+        Ok(("", e)) => Ok(e),
+        _ => bail!("Cannot parse \"{}\"", line),
+    }
 }
 
 impl FromStr for TimeEntry {
@@ -468,63 +757,182 @@ impl Display for TimeEntry {
     }
 }
 
+// The state of a data file when it was read, to detect modifications by other programs.
+#[derive(Clone, PartialEq, Debug)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl Stamp {
+    fn new(file: &Path, data: &str) -> Result<Stamp> {
+        let metadata = fs::metadata(file)?;
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+
+        Ok(Stamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hasher.finish(),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct LoadedFile {
+    entries: usize,
+    stamp: Stamp,
+}
+
+/// Error returned when storing a Work, if a data file was modified since the Work was loaded.
+#[derive(Debug)]
+pub struct ConflictError {
+    file: PathBuf,
+}
+
+impl ConflictError {
+    /// Return the path of the modified data file.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+}
+
+impl Display for ConflictError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} was modified since it was loaded",
+            self.file.display()
+        )
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+// Write the data to a temporary file next to the given file and return its path. Renaming it to
+// the file replaces the file at once, so that it is never left half-written.
+fn write_tmp(file: &Path, data: &str) -> Result<PathBuf> {
+    let mut name = OsString::from(".");
+    name.push(file.file_name().unwrap());
+    name.push(".tmp");
+    let tmp = file.with_file_name(name);
+
+    let mut f = File::create(&tmp)?;
+    f.write_all(data.as_bytes())?;
+    f.sync_all()?;
+
+    Ok(tmp)
+}
+
+// An entry added to, removed from or replaced in a Work.
+#[derive(Clone, Debug)]
+struct Action {
+    before: Option<TimeEntry>,
+    after: Option<TimeEntry>,
+}
+
+impl Action {
+    fn json(entry: &Option<TimeEntry>) -> String {
+        entry.as_ref().map(|e| e.json()).unwrap_or_default()
+    }
+}
+
+/// How `Work::by_tag` counts the time of the entries that have several tags.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TagAttribution {
+    /// The whole time of the entry counts for each of its tags, so the totals of the tags add up to
+    /// more than the tracked time. This is what `timew summary` does when filtering on a tag.
+    Full,
+    /// The time of the entry is shared equally between its tags.
+    Split,
+    /// The time of the entry only counts for its first tag.
+    First,
+}
+
+// Share the duration equally between the given number of parts. The remainder of the division goes
+// to the first part, so that the parts add up to the duration.
+pub(crate) fn split_duration(duration: Duration, parts: usize) -> Vec<Duration> {
+    if parts == 0 {
+        return vec![];
+    }
+    let share = duration / parts as i32;
+    let mut shares = vec![share; parts];
+    shares[0] = duration - share * (parts as i32 - 1);
+    shares
+}
+
 /// Represent the work done, providing a list of time entries.
+#[derive(Clone)]
 pub struct Work {
     entries: Vec<TimeEntry>,
+    // The data files that were read. It is only set when the whole database is loaded: storing a
+    // filtered Work would drop the entries outside of that Range.
+    files: Option<BTreeMap<PathBuf, LoadedFile>>,
+    // The entries removed, added and replaced since the Work was loaded, in order, for `tags.data`
+    // and `undo.data`.
+    actions: Vec<Action>,
+    // The Range the Work was loaded for, durations only count the time in that Range.
+    range: Option<Range>,
 }
 
 impl Work {
-    fn load_entries_from_file(file: &Path) -> Result<Vec<TimeEntry>> {
+    fn load_entries_from_file(file: &Path) -> Result<(Vec<TimeEntry>, Stamp)> {
         let mut entries = vec![];
-        let data = File::open(file)?;
-
-        for line in io::BufReader::new(data).lines() {
-            entries.push(line?.parse()?);
+        let data = fs::read_to_string(file)?;
+        let stamp = Stamp::new(file, &data)?;
+
+        // The whole line must be parsed: an entry with an unterminated tag or annotation would be
+        // written back without it.
+        for (i, line) in data.lines().enumerate() {
+            let location = Location::new(file.to_path_buf(), i + 1);
+            let mut entry =
+                parse_line(line).with_context(|| format!("Invalid entry at {}", location))?;
+            entry.location = Some(location);
+            entries.push(entry);
         }
 
-        Ok(entries)
+        Ok((entries, stamp))
     }
 
     /// Load entries from the given timewarrior database at data_path.
     /// If Range is given, only the entries in that range are added to the Work.
     pub fn load_range(data_path: &Path, range: Option<Range>) -> Result<Work> {
-        let mut entries = vec![];
-
-        let file_re = Regex::new(r"^(?P<y>\d{4})-(?P<m>\d{2}).data$").unwrap();
+        Work::load_filter(data_path, &Filter::new(range))
+    }
 
-        for file in read_dir(data_path)? {
-            let file = file?;
-            for _ in file_re.captures_iter(&file.file_name().to_string_lossy()) {
-                entries.append(&mut Work::load_entries_from_file(&file.path())?);
-            }
+    /// Load the entries matching the Filter from the given timewarrior database at data_path. The
+    /// entries keep the IDs they have in the whole database.
+    pub fn load_filter(data_path: &Path, filter: &Filter) -> Result<Work> {
+        let mut entries = vec![];
+        let mut files = BTreeMap::new();
+
+        for file in data_files(data_path)? {
+            let (mut file_entries, stamp) = Work::load_entries_from_file(&file)?;
+            files.insert(
+                file,
+                LoadedFile {
+                    entries: file_entries.len(),
+                    stamp,
+                },
+            );
+            entries.append(&mut file_entries);
         }
 
-        entries.sort_by(|a, b| {
-            if a.range.from > b.range.from {
-                Ordering::Less
-            } else if a.range.from < b.range.from {
-                Ordering::Greater
-            } else {
-                Ordering::Equal
-            }
-        });
+        let mut work = Work {
+            entries,
+            files: Some(files),
+            actions: vec![],
+            range: filter.range().copied(),
+        };
+        work.renumber();
 
-        let mut i = 1;
-        for e in &mut entries {
-            e.id = i;
-            i += 1;
+        if !filter.is_empty() {
+            work.entries.retain(|e| filter.matches(e));
+            work.files = None;
         }
 
-        return if let Some(r) = range {
-            Ok(Work {
-                entries: entries
-                    .into_iter()
-                    .filter(|e| e.range.intersection(&r).is_some())
-                    .collect(),
-            })
-        } else {
-            Ok(Work { entries })
-        };
+        Ok(work)
     }
 
     /// Same as `load_range` but loads all entries.
@@ -537,77 +945,970 @@ impl Work {
         &self.entries
     }
 
-    /// Return the duration of all the entries.
+    /// Return the entry with the given ID, if any.
+    pub fn get(&self, id: usize) -> Option<&TimeEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    /// Return the Range the Work was loaded for, if any.
+    pub fn range(&self) -> Option<&Range> {
+        self.range.as_ref()
+    }
+
+    /// Return the part of the Range of the entry that is in the Range the Work was loaded for. An
+    /// entry that started before midnight is clipped to midnight in a Work loaded for a day. An
+    /// open entry is clipped to now when the Range ends later, and stays open when the Range is
+    /// open.
+    pub fn clipped(&self, entry: &TimeEntry) -> Option<Range> {
+        self.clipped_at(entry, Utc::now())
+    }
+
+    // Like `clipped`, at the given date/time.
+    fn clipped_at(&self, entry: &TimeEntry, now: DateTime<Utc>) -> Option<Range> {
+        let clipped = match &self.range {
+            Some(r) => entry.range.intersection(r)?,
+            None => return Some(entry.range),
+        };
+
+        match clipped.to {
+            Some(to) if entry.range.is_open() && to > now => {
+                Range::new(clipped.from, Some(now)).ok()
+            }
+            _ => Some(clipped),
+        }
+    }
+
+    /// Return the duration of all the entries, only counting the time in the Range the Work was
+    /// loaded for. See `unclipped_duration` for the whole duration of the entries.
     pub fn duration(&self) -> Duration {
         self.entries()
             .iter()
-            .fold(Duration::zero(), |a, t| a + t.range.duration())
+            .filter_map(|e| self.clipped(e))
+            .fold(Duration::zero(), |a, r| a + r.duration())
     }
-}
 
-impl Display for Work {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} entries loaded", self.entries.len())
+    /// Return the whole duration of all the entries, including the time outside of the Range the
+    /// Work was loaded for.
+    pub fn unclipped_duration(&self) -> Duration {
+        self.entries()
+            .iter()
+            .fold(Duration::zero(), |a, t| a + t.range.duration())
     }
-}
-
-#[cfg(test)]
-mod range_tests {
-    use crate::data::Range;
-    use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 
-    fn parse_date_time(date: &str) -> DateTime<Utc> {
-        let d = NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ").unwrap();
-        DateTime::<Utc>::from_utc(d, Utc)
+    // Return the time of the entry that is in the Range the Work was loaded for, an open entry
+    // ending now.
+    fn clipped_closed(&self, entry: &TimeEntry, now: DateTime<Utc>) -> Option<Range> {
+        self.clipped_at(entry, now)
+            .and_then(|r| Range::new(r.from, Some(r.to.unwrap_or(now))).ok())
     }
 
-    #[test]
-    fn test_range_parse() {
-        // Correct range
-        assert_eq!(
-            "20220101T120000Z - 20220101T130000Z"
-                .parse::<Range>()
-                .unwrap(),
-            Range {
-                from: parse_date_time("20220101T120000Z"),
-                to: Some(parse_date_time("20220101T130000Z"))
+    // Split the closed Range at the boundaries of the periods it spans, and return its part in
+    // each period. The period function returns the key and the Range of the period containing the
+    // given local day.
+    fn split_periods<Tz: TimeZone>(
+        range: &Range,
+        tz: &Tz,
+        period: impl Fn(NaiveDate) -> Result<(NaiveDate, Range)>,
+    ) -> Result<Vec<(NaiveDate, Range)>> {
+        let mut parts = vec![];
+        let end = range.to.unwrap_or(range.from);
+        let mut day = range.from.with_timezone(tz).date_naive();
+        loop {
+            let (key, p) = period(day)?;
+            if let Some(part) = range.intersection(&p) {
+                parts.push((key, part));
             }
-        );
-
-        // from > to
-        assert!("20220101T130000Z - 20220101T120000Z"
-            .parse::<Range>()
-            .is_err());
 
-        // to not specified
-        assert_eq!(
-            "20220101T120000Z".parse::<Range>().unwrap(),
-            Range {
-                from: parse_date_time("20220101T120000Z"),
-                to: None
+            let next = p.to.unwrap_or(end);
+            if next >= end {
+                break;
             }
-        );
+            day = next.with_timezone(tz).date_naive();
+        }
 
-        // to not specified but from > now
+        Ok(parts)
+    }
+
+    // Sum the time of the entries in each period, the entries that span several periods being
+    // split at their boundaries.
+    fn by_period<Tz: TimeZone>(
+        &self,
+        tz: &Tz,
+        period: impl Fn(NaiveDate) -> Result<(NaiveDate, Range)>,
+    ) -> Result<BTreeMap<NaiveDate, Duration>> {
         let now = Utc::now();
-        let tomorrow = now + Duration::days(1);
-        let range = tomorrow.format("%Y%m%dT%H%M%SZ").to_string();
-        assert!(range.parse::<Range>().is_err(),);
+        let mut totals = BTreeMap::new();
+        for range in self
+            .entries
+            .iter()
+            .filter_map(|e| self.clipped_closed(e, now))
+        {
+            for (key, part) in Work::split_periods(&range, tz, &period)? {
+                *totals.entry(key).or_insert(Duration::zero()) += part.duration();
+            }
+        }
 
-        // Wrong format
-        assert!("１日１月２０２２年".parse::<Range>().is_err())
+        Ok(totals)
     }
 
-    #[test]
-    fn test_range_split() {
-        let input1: Range = "20220101T120000Z - 20220101T130000Z".parse().unwrap();
-        let output1: Range = "20220101T120000Z - 20220101T123000Z".parse().unwrap();
-        let output2: Range = "20220101T123000Z - 20220101T130000Z".parse().unwrap();
+    /// Return the time of the entry on each day in the given timezone, only counting the time in
+    /// the Range the Work was loaded for. An open entry ends now.
+    pub fn entry_days_in<Tz: TimeZone>(
+        &self,
+        entry: &TimeEntry,
+        tz: &Tz,
+    ) -> Result<Vec<(NaiveDate, Duration)>> {
+        Ok(self
+            .entry_parts_in(entry, tz)?
+            .into_iter()
+            .map(|(day, part)| (day, part.duration()))
+            .collect())
+    }
 
-        assert_eq!(input1.split().unwrap(), (output1, output2));
+    /// Return the part of the entry on each day in the given timezone, only keeping the time in
+    /// the Range the Work was loaded for. The last part of an entry that is still running is open.
+    pub fn entry_parts_in<Tz: TimeZone>(
+        &self,
+        entry: &TimeEntry,
+        tz: &Tz,
+    ) -> Result<Vec<(NaiveDate, Range)>> {
+        let now = Utc::now();
+        let range = match self.clipped_closed(entry, now) {
+            Some(r) => r,
+            None => return Ok(vec![]),
+        };
 
-        // Check that 1s duration is not split
-        let input1: Range = "20220101T120000Z - 20220101T120001Z".parse().unwrap();
+        let mut parts = Work::split_periods(&range, tz, |day| Ok((day, Range::day_in(day, tz)?)))?;
+        if entry.range.is_open() && range.to == Some(now) {
+            if let Some((_, last)) = parts.last_mut() {
+                last.to = None;
+            }
+        }
+
+        Ok(parts)
+    }
+
+    /// Return the time tracked on each day in the given timezone. Entries that span midnight are
+    /// split between both days, and only the time in the Range the Work was loaded for is counted.
+    pub fn by_day_in<Tz: TimeZone>(&self, tz: &Tz) -> Result<BTreeMap<NaiveDate, Duration>> {
+        self.by_period(tz, |day| Ok((day, Range::day_in(day, tz)?)))
+    }
+
+    /// Return the time tracked on each local day
+    pub fn by_day(&self) -> Result<BTreeMap<NaiveDate, Duration>> {
+        self.by_day_in(&Local)
+    }
+
+    /// Return the time tracked in each week starting on `weekstart` in the given timezone, by the
+    /// first day of the week
+    pub fn by_week_in<Tz: TimeZone>(
+        &self,
+        weekstart: Weekday,
+        tz: &Tz,
+    ) -> Result<BTreeMap<NaiveDate, Duration>> {
+        self.by_period(tz, |day| {
+            Ok((
+                last_weekday(day, weekstart),
+                Range::week_starting_in(day, weekstart, tz)?,
+            ))
+        })
+    }
+
+    /// Return the time tracked in each local week, starting on `weekstart`, by the first day of
+    /// the week
+    pub fn by_week(&self, weekstart: Weekday) -> Result<BTreeMap<NaiveDate, Duration>> {
+        self.by_week_in(weekstart, &Local)
+    }
+
+    /// Return the time tracked in each month in the given timezone, by the first day of the month
+    pub fn by_month_in<Tz: TimeZone>(&self, tz: &Tz) -> Result<BTreeMap<NaiveDate, Duration>> {
+        self.by_period(tz, |day| {
+            Ok((day.with_day(1).unwrap(), Range::month_in(day, tz)?))
+        })
+    }
+
+    /// Return the time tracked in each local month, by the first day of the month
+    pub fn by_month(&self) -> Result<BTreeMap<NaiveDate, Duration>> {
+        self.by_month_in(&Local)
+    }
+
+    /// Return the time tracked for each tag, the time of the entries with several tags being
+    /// counted according to the given attribution. Entries without tags are not counted.
+    pub fn by_tag(&self, attribution: TagAttribution) -> BTreeMap<String, Duration> {
+        let now = Utc::now();
+        let mut totals = BTreeMap::new();
+        for entry in &self.entries {
+            let time = match self.clipped(entry) {
+                Some(r) => r.to.unwrap_or(now) - r.from,
+                None => continue,
+            };
+            let tags = match attribution {
+                TagAttribution::Full | TagAttribution::Split => &entry.tags[..],
+                TagAttribution::First => &entry.tags[..entry.tags.len().min(1)],
+            };
+            let times = match attribution {
+                TagAttribution::Split => split_duration(time, tags.len()),
+                _ => vec![time; tags.len()],
+            };
+
+            for (tag, time) in tags.iter().zip(times) {
+                *totals.entry(tag.clone()).or_insert(Duration::zero()) += time;
+            }
+        }
+
+        totals
+    }
+
+    /// Return the gaps in the given Range: the times that are neither tracked by an entry nor
+    /// part of the excluded Ranges. An open Range ends now.
+    pub fn gaps(&self, range: &Range, excluded: &[Range]) -> Vec<Range> {
+        let busy: RangeSet = self
+            .entries
+            .iter()
+            .map(|e| e.range)
+            .chain(excluded.iter().copied())
+            .collect();
+        let bounds = Range {
+            to: Some(range.to.unwrap_or_else(Utc::now)),
+            ..*range
+        };
+
+        busy.complement(&bounds).ranges()
+    }
+
+    // Sort the entries from the most recent to the oldest one and give them their IDs.
+    fn renumber(&mut self) {
+        self.entries.sort_by_key(|e| Reverse(e.range.from));
+
+        for (i, e) in self.entries.iter_mut().enumerate() {
+            e.id = i + 1;
+        }
+    }
+
+    /// Remove the entry with the given ID and return it. The IDs of the remaining entries are
+    /// updated.
+    pub(crate) fn remove(&mut self, id: usize) -> Result<TimeEntry> {
+        let entry = self.take(id)?;
+        self.actions.push(Action {
+            before: Some(entry.clone()),
+            after: None,
+        });
+        self.renumber();
+
+        Ok(entry)
+    }
+
+    fn take(&mut self, id: usize) -> Result<TimeEntry> {
+        match self.entries.iter().position(|e| e.id == id) {
+            Some(pos) => Ok(self.entries.remove(pos)),
+            None => bail!("ID '@{}' does not correspond to any tracking.", id),
+        }
+    }
+
+    /// Add a new entry. The IDs of all entries are updated.
+    pub(crate) fn insert(&mut self, entry: TimeEntry) {
+        let entry = TimeEntry {
+            location: None,
+            ..entry
+        };
+        self.actions.push(Action {
+            before: None,
+            after: Some(entry.clone()),
+        });
+        self.entries.push(entry);
+        self.renumber();
+    }
+
+    /// Replace the entry with the given ID and return the previous entry. The IDs of all entries
+    /// are updated.
+    pub(crate) fn replace(&mut self, id: usize, entry: TimeEntry) -> Result<TimeEntry> {
+        let previous = self.take(id)?;
+        let entry = TimeEntry {
+            location: None,
+            ..entry
+        };
+        self.actions.push(Action {
+            before: Some(previous.clone()),
+            after: Some(entry.clone()),
+        });
+        self.entries.push(entry);
+        self.renumber();
+
+        Ok(previous)
+    }
+
+    /// Return true if entries were added or removed since the Work was loaded.
+    pub(crate) fn is_modified(&self) -> bool {
+        !self.actions.is_empty()
+    }
+
+    /// Return the net changes since the Work was loaded, as pairs of an entry as it was loaded and
+    /// the entry that replaces it, with its current ID. An entry replaced several times appears
+    /// once. Added entries have no previous entry, and removed entries have no new entry. Fail if
+    /// an action replaces an entry that was neither loaded nor added before.
+    pub(crate) fn changes(&self) -> Result<Vec<(Option<TimeEntry>, Option<TimeEntry>)>> {
+        let same = |a: &TimeEntry, b: &TimeEntry| {
+            a.range == b.range && a.tags == b.tags && a.annotation == b.annotation
+        };
+
+        // The entries added since the Work was loaded, with the loaded entry they replace.
+        let mut added: Vec<(Option<TimeEntry>, TimeEntry)> = vec![];
+        let mut removed = vec![];
+        for action in &self.actions {
+            let origin = match &action.before {
+                Some(b) if b.location.is_some() => Some(b.clone()),
+                Some(b) => match added.iter().position(|(_, a)| same(a, b)) {
+                    Some(pos) => added.remove(pos).0,
+                    None => bail!("The replaced entry {} was never added", b),
+                },
+                None => None,
+            };
+            match &action.after {
+                Some(a) => added.push((origin, a.clone())),
+                None => removed.extend(origin),
+            }
+        }
+
+        let mut changes: Vec<(Option<TimeEntry>, Option<TimeEntry>)> =
+            removed.into_iter().map(|e| (Some(e), None)).collect();
+        for (origin, entry) in added {
+            if origin.as_ref().is_some_and(|o| same(o, &entry)) {
+                continue;
+            }
+            let current = self
+                .entries
+                .iter()
+                .find(|e| e.location.is_none() && same(e, &entry))
+                .cloned();
+            changes.push((origin, current));
+        }
+
+        Ok(changes)
+    }
+
+    /// Return the files of the database at data_path that changed since the Work was loaded, with
+    /// their new content. Only the data files that lost or gained entries are returned. New
+    /// entries go to the data file of the month they start in.
+    pub(crate) fn changed_files(&self, data_path: &Path) -> Result<BTreeMap<PathBuf, String>> {
+        let loaded = match &self.files {
+            Some(f) => f,
+            None => bail!("Cannot store a Work that was loaded for a specific Range"),
+        };
+
+        let mut files: BTreeMap<PathBuf, Vec<&TimeEntry>> =
+            loaded.keys().map(|f| (f.clone(), vec![])).collect();
+        let mut dirty = BTreeSet::new();
+
+        for entry in &self.entries {
+            let file = match &entry.location {
+                Some(l) => l.file.clone(),
+                None => {
+                    let f = data_path.join(entry.data_file_name());
+                    dirty.insert(f.clone());
+                    f
+                }
+            };
+            files.entry(file).or_default().push(entry);
+        }
+
+        let mut changed = BTreeMap::new();
+        for (file, mut entries) in files {
+            if !dirty.contains(&file) && loaded.get(&file).map(|f| f.entries) == Some(entries.len())
+            {
+                continue;
+            }
+
+            entries.sort_by_key(|e| e.range.from);
+            let mut data = String::new();
+            for entry in entries {
+                data.push_str(&entry.serialize());
+                data.push('\n');
+            }
+            changed.insert(file, data);
+        }
+
+        let mut tag_counts: BTreeMap<&str, i64> = BTreeMap::new();
+        for action in &self.actions {
+            for (entry, delta) in [(&action.before, -1), (&action.after, 1)] {
+                for tag in entry.iter().flat_map(|e| &e.tags) {
+                    *tag_counts.entry(tag).or_default() += delta;
+                }
+            }
+        }
+
+        // Without tags.data, timew rebuilds it from the data files.
+        if tag_counts.values().any(|c| *c != 0) && TagsData::path(data_path).exists() {
+            let mut tags = TagsData::load(data_path)?;
+            for (tag, delta) in tag_counts {
+                tags.add(tag, delta);
+            }
+            changed.insert(TagsData::path(data_path), tags.serialize());
+        }
+
+        // All the actions form one transaction of the timew undo journal.
+        if !self.actions.is_empty() {
+            let undo = data_path.join("undo.data");
+            let mut data = match fs::read_to_string(&undo) {
+                Ok(d) => d,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(e.into()),
+            };
+            data.push_str("txn:\n");
+            for action in &self.actions {
+                data.push_str(&format!(
+                    "  type: interval\n  before: {}\n  after: {}\n",
+                    Action::json(&action.before),
+                    Action::json(&action.after)
+                ));
+            }
+            changed.insert(undo, data);
+        }
+
+        Ok(changed)
+    }
+
+    /// Write the files returned by `changed_files` to data_path: the data files, `tags.data` and
+    /// `undo.data`.
+    ///
+    /// The database is locked with its `.lock` file while it is written. timew itself ignores that
+    /// file, so the lock only keeps the writers using this crate apart.
+    ///
+    /// All the files are first written to temporary files, that then replace them. If one of them
+    /// cannot be replaced, the files already replaced are restored, so that either all or none of
+    /// the files are written. If a data file was modified, created or removed since the Work was
+    /// loaded, nothing is written and a `ConflictError` is returned.
+    pub(crate) fn store(&self, data_path: &Path) -> Result<()> {
+        let lock = File::create(data_path.join(".lock"))?;
+        lock.lock()?;
+
+        let changed = self.changed_files(data_path)?;
+        let loaded = self.files.as_ref().unwrap();
+        for file in data_files(data_path)?
+            .iter()
+            .chain(loaded.keys())
+            .chain(changed.keys())
+        {
+            if file == &TagsData::path(data_path) || file == &data_path.join("undo.data") {
+                continue;
+            }
+            let current = match fs::read_to_string(file) {
+                Ok(data) => Some(Stamp::new(file, &data)?),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e.into()),
+            };
+            if current.as_ref() != loaded.get(file).map(|f| &f.stamp) {
+                return Err(ConflictError { file: file.clone() }.into());
+            }
+        }
+
+        let mut staged = vec![];
+        for (file, data) in &changed {
+            match write_tmp(file, data) {
+                Ok(tmp) => staged.push((tmp, file)),
+                Err(e) => {
+                    for (tmp, _) in staged {
+                        let _ = fs::remove_file(tmp);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut previous = vec![];
+        for (_, file) in &staged {
+            previous.push(fs::read_to_string(file).ok());
+        }
+
+        for (i, (tmp, file)) in staged.iter().enumerate() {
+            if let Err(e) = fs::rename(tmp, file) {
+                for ((_, file), data) in staged.iter().zip(&previous).take(i) {
+                    let _ = match data {
+                        Some(d) => write_tmp(file, d).and_then(|t| Ok(fs::rename(t, file)?)),
+                        None => fs::remove_file(file).map_err(|e| e.into()),
+                    };
+                }
+                for (tmp, _) in &staged[i..] {
+                    let _ = fs::remove_file(tmp);
+                }
+                return Err(e.into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Work {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} entries loaded", self.entries.len())
+    }
+}
+
+/*
+Parse the content of the tags.data file. It is a JSON object giving the usage count of each tag:
+{
+  "tag1":{"count":3},
+  "tag 2":{"count":1}
+}
+ */
+fn parse_tags_data(input: &str) -> NomResult<&str, Vec<(String, u64)>> {
+    delimited(
+        preceded(multispace0, nom_char('{')),
+        separated_list0(
+            nom_char(','),
+            separated_pair(
+                delimited(multispace0, parse_quoted, multispace0),
+                nom_char(':'),
+                delimited(
+                    tuple((
+                        multispace0,
+                        nom_char('{'),
+                        multispace0,
+                        tag("\"count\""),
+                        multispace0,
+                        nom_char(':'),
+                        multispace0,
+                    )),
+                    u64,
+                    tuple((multispace0, nom_char('}'), multispace0)),
+                ),
+            ),
+        ),
+        tuple((multispace0, nom_char('}'), multispace0)),
+    )(input)
+}
+
+/// Represent the `tags.data` file of the database: the number of entries that used each tag.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct TagsData {
+    counts: BTreeMap<String, u64>,
+}
+
+impl TagsData {
+    fn path(data_path: &Path) -> PathBuf {
+        data_path.join("tags.data")
+    }
+
+    /// Load the tags.data file of the given timewarrior database at data_path. A missing file
+    /// gives an empty TagsData.
+    pub fn load(data_path: &Path) -> Result<TagsData> {
+        let path = TagsData::path(data_path);
+        if !path.exists() {
+            return Ok(TagsData::default());
+        }
+
+        let data = fs::read_to_string(&path)?;
+        match parse_tags_data(&data) {
+            Ok(("", counts)) => Ok(TagsData {
+                counts: counts.into_iter().collect(),
+            }),
+            _ => bail!("Cannot parse {}", path.display()),
+        }
+    }
+
+    /// Return the usage count of the given tag.
+    pub fn count(&self, tag: &str) -> u64 {
+        self.counts.get(tag).copied().unwrap_or(0)
+    }
+
+    /// Return an iterator over the known tags and their usage count, sorted by tag.
+    pub fn tags(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.counts.iter().map(|(t, c)| (t.as_str(), *c))
+    }
+
+    // Like timew, tags are kept in the file when their count drops to 0.
+    fn add(&mut self, tag: &str, delta: i64) {
+        let count = self.counts.entry(tag.to_string()).or_default();
+        *count = count.saturating_add_signed(delta);
+    }
+
+    fn serialize(&self) -> String {
+        let mut data = String::from("{");
+        for (i, (tag, count)) in self.counts.iter().enumerate() {
+            if i > 0 {
+                data.push(',');
+            }
+            data.push_str(&format!("\n  \"{}\":{{\"count\":{}}}", escape(tag), count));
+        }
+        if !self.counts.is_empty() {
+            data.push('\n');
+        }
+        data.push('}');
+
+        data
+    }
+}
+
+/// A temporary database directory for the tests, that is removed when dropped, even when the test
+/// fails. A directory left by a previous run is removed first.
+#[cfg(test)]
+pub(crate) struct TestDir(PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub(crate) fn new(name: &str) -> TestDir {
+        let path =
+            std::env::temp_dir().join(format!("timewarrior-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir(path)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod range_tests {
+    use crate::data::{previous_month, Range, TimeEntry};
+    use chrono::{
+        DateTime, Duration, FixedOffset, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime,
+        TimeZone, Utc, Weekday,
+    };
+
+    fn parse_date_time(date: &str) -> DateTime<Utc> {
+        let d = NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ").unwrap();
+        Utc.from_utc_datetime(&d)
+    }
+
+    #[test]
+    fn test_range_parse() {
+        // Correct range
+        assert_eq!(
+            "20220101T120000Z - 20220101T130000Z"
+                .parse::<Range>()
+                .unwrap(),
+            Range {
+                from: parse_date_time("20220101T120000Z"),
+                to: Some(parse_date_time("20220101T130000Z"))
+            }
+        );
+
+        // from > to
+        assert!("20220101T130000Z - 20220101T120000Z"
+            .parse::<Range>()
+            .is_err());
+
+        // to not specified
+        assert_eq!(
+            "20220101T120000Z".parse::<Range>().unwrap(),
+            Range {
+                from: parse_date_time("20220101T120000Z"),
+                to: None
+            }
+        );
+
+        // to not specified but from > now
+        let now = Utc::now();
+        let tomorrow = now + Duration::days(1);
+        let range = tomorrow.format("%Y%m%dT%H%M%SZ").to_string();
+        assert!(range.parse::<Range>().is_err(),);
+
+        // Wrong format
+        assert!("１日１月２０２２年".parse::<Range>().is_err())
+    }
+
+    #[test]
+    fn test_range_hints() {
+        // 17 August 2022 is a Wednesday
+        let today = date(2022, 8, 17);
+        let period =
+            |p: &str, weekstart: Weekday| Range::from_period_in(p, today, weekstart, &Utc).unwrap();
+        let day = |d: u32| format!("202208{:02}T000000Z", d);
+
+        for (p, from, to) in [
+            ("today", day(17), day(18)),
+            ("yesterday", day(16), day(17)),
+            ("week", day(15), day(22)),
+            ("lastweek", day(8), day(15)),
+            ("fortnight", day(8), day(22)),
+            ("month", day(1), "20220901T000000Z".to_string()),
+            ("lastmonth", "20220701T000000Z".to_string(), day(1)),
+            (
+                "quarter",
+                "20220701T000000Z".to_string(),
+                "20221001T000000Z".to_string(),
+            ),
+            (
+                "lastquarter",
+                "20220401T000000Z".to_string(),
+                "20220701T000000Z".to_string(),
+            ),
+            (
+                "year",
+                "20220101T000000Z".to_string(),
+                "20230101T000000Z".to_string(),
+            ),
+            (
+                "lastyear",
+                "20210101T000000Z".to_string(),
+                "20220101T000000Z".to_string(),
+            ),
+            ("monday", day(15), day(16)),
+            ("wednesday", day(17), day(18)),
+            ("sunday", day(14), day(15)),
+        ] {
+            assert_eq!(period(p, Weekday::Mon), range(&from, &to), "{}", p);
+        }
+
+        // Weeks start on the given day
+        assert_eq!(period("week", Weekday::Sun), range(&day(14), &day(21)));
+        assert_eq!(period("fortnight", Weekday::Sun), range(&day(7), &day(21)));
+
+        let all = period("all", Weekday::Mon);
+        assert_eq!((all.from(), all.to()), (DateTime::UNIX_EPOCH, None));
+
+        assert!(Range::from_period("someday", Weekday::Mon).is_err());
+        // Periods are not ranges of data files
+        assert!(":week".parse::<Range>().is_err());
+    }
+
+    // The timezone of Paris in 2022: UTC+01:00, and UTC+02:00 from 27 March to 30 October
+    #[derive(Clone, Copy, Debug)]
+    struct Paris;
+
+    impl TimeZone for Paris {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Paris {
+            Paris
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let summer = parse_date_time("20220327T010000Z").naive_utc() <= *utc
+                && *utc < parse_date_time("20221030T010000Z").naive_utc();
+            FixedOffset::east_opt(if summer { 7200 } else { 3600 }).unwrap()
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let offsets: Vec<FixedOffset> = [7200, 3600]
+                .iter()
+                .map(|o| FixedOffset::east_opt(*o).unwrap())
+                .filter(|o| self.offset_from_utc_datetime(&(*local - *o)) == *o)
+                .collect();
+            match offsets[..] {
+                [o] => LocalResult::Single(o),
+                [a, b] => LocalResult::Ambiguous(a, b),
+                _ => LocalResult::None,
+            }
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn range(from: &str, to: &str) -> Range {
+        Range::new(parse_date_time(from), Some(parse_date_time(to))).unwrap()
+    }
+
+    #[test]
+    fn test_range_calendar_offsets() {
+        for (offset, from, to) in [
+            (5 * 3600 + 1800, "20220710T183000Z", "20220711T183000Z"),
+            (-8 * 3600, "20220711T080000Z", "20220712T080000Z"),
+            (14 * 3600, "20220710T100000Z", "20220711T100000Z"),
+            (0, "20220711T000000Z", "20220712T000000Z"),
+        ] {
+            let tz = FixedOffset::east_opt(offset).unwrap();
+            let late = tz.with_ymd_and_hms(2022, 7, 11, 23, 30, 0).unwrap();
+            let early = tz.with_ymd_and_hms(2022, 7, 11, 0, 0, 0).unwrap();
+            assert_eq!(Range::day(&late).unwrap(), range(from, to));
+            assert_eq!(Range::day(&early).unwrap(), range(from, to));
+            assert_eq!(
+                Range::day_in(date(2022, 7, 11), &tz).unwrap(),
+                range(from, to)
+            );
+
+            // 11 July 2022 is a Monday
+            let week = Range::week(&(late + Duration::days(6))).unwrap();
+            assert_eq!(week.from(), parse_date_time(from));
+            assert_eq!(week.duration(), Duration::weeks(1));
+
+            let month = Range::month(&late).unwrap();
+            assert_eq!(month.from(), parse_date_time(from) - Duration::days(10));
+            assert_eq!(month.duration(), Duration::days(31));
+        }
+
+        let utc = Utc.with_ymd_and_hms(2022, 2, 10, 12, 0, 0).unwrap();
+        assert_eq!(
+            Range::month(&utc).unwrap(),
+            range("20220201T000000Z", "20220301T000000Z")
+        );
+    }
+
+    #[test]
+    fn test_range_calendar_dst() {
+        let spring = Range::day_in(date(2022, 3, 27), &Paris).unwrap();
+        assert_eq!(spring, range("20220326T230000Z", "20220327T220000Z"));
+        assert_eq!(spring.duration(), Duration::hours(23));
+
+        let autumn = Range::day(&Paris.with_ymd_and_hms(2022, 10, 30, 23, 0, 0).unwrap()).unwrap();
+        assert_eq!(autumn, range("20221029T220000Z", "20221030T230000Z"));
+        assert_eq!(autumn.duration(), Duration::hours(25));
+
+        assert_eq!(
+            Range::week_in(date(2022, 3, 27), &Paris).unwrap(),
+            range("20220320T230000Z", "20220327T220000Z")
+        );
+        assert_eq!(
+            Range::week_in(date(2022, 3, 28), &Paris).unwrap(),
+            range("20220327T220000Z", "20220403T220000Z")
+        );
+        assert_eq!(
+            Range::month_in(date(2022, 10, 15), &Paris).unwrap(),
+            range("20220930T220000Z", "20221031T230000Z")
+        );
+        assert_eq!(
+            Range::month_in(date(2022, 3, 1), &Paris).unwrap(),
+            range("20220228T230000Z", "20220331T220000Z")
+        );
+    }
+
+    #[test]
+    fn test_range_months() {
+        assert_eq!(previous_month(date(2022, 1, 31)), date(2021, 12, 1));
+        assert_eq!(previous_month(date(2022, 3, 31)), date(2022, 2, 1));
+        assert_eq!(previous_month(date(2022, 12, 1)), date(2022, 11, 1));
+        assert!(Range::last_month().is_ok());
+
+        for (year, month, days) in [
+            (2022, 1, 31),
+            (2022, 4, 30),
+            (2022, 12, 31),
+            (2023, 2, 28),
+            (2024, 2, 29),
+            (1900, 2, 28),
+            (2000, 2, 29),
+        ] {
+            let range = Range::year_month_in(year, month, &Utc).unwrap();
+            assert_eq!(range.from().date_naive(), date(year, month, 1));
+            assert_eq!(range.duration(), Duration::days(days));
+        }
+        assert_eq!(
+            Range::year_month_in(2022, 12, &Utc).unwrap(),
+            range("20221201T000000Z", "20230101T000000Z")
+        );
+        assert!(Range::year_month(2022, 13).is_err());
+        assert!(Range::year_month(2022, 0).is_err());
+        assert_eq!(
+            Range::year_month_in(2022, 3, &Paris).unwrap(),
+            range("20220228T230000Z", "20220331T220000Z")
+        );
+    }
+
+    #[test]
+    fn test_range_iso_weeks() {
+        assert_eq!(
+            Range::iso_week_in(2022, 28, &Utc).unwrap(),
+            range("20220711T000000Z", "20220718T000000Z")
+        );
+        assert_eq!(
+            Range::iso_week_in(2020, 53, &Utc).unwrap(),
+            range("20201228T000000Z", "20210104T000000Z")
+        );
+        assert_eq!(
+            Range::iso_week_in(2026, 1, &Utc).unwrap(),
+            range("20251229T000000Z", "20260105T000000Z")
+        );
+        assert_eq!(
+            Range::iso_week_in(2022, 13, &Paris).unwrap(),
+            range("20220327T220000Z", "20220403T220000Z")
+        );
+        assert!(Range::iso_week(2021, 53).is_err());
+        assert!(Range::iso_week(2022, 0).is_err());
+    }
+
+    #[test]
+    fn test_range_weekstart() {
+        // 13 July 2022 is a Wednesday
+        assert_eq!(
+            Range::week_starting_in(date(2022, 7, 13), Weekday::Sun, &Utc).unwrap(),
+            range("20220710T000000Z", "20220717T000000Z")
+        );
+        assert_eq!(
+            Range::week_starting_in(date(2022, 7, 10), Weekday::Sun, &Utc).unwrap(),
+            range("20220710T000000Z", "20220717T000000Z")
+        );
+        assert_eq!(
+            Range::week_starting_in(date(2022, 7, 9), Weekday::Sun, &Utc).unwrap(),
+            range("20220703T000000Z", "20220710T000000Z")
+        );
+        assert_eq!(
+            Range::week_starting_in(date(2022, 7, 13), Weekday::Thu, &Paris).unwrap(),
+            range("20220706T220000Z", "20220713T220000Z")
+        );
+        assert_eq!(
+            Range::week_in(date(2022, 7, 17), &Utc).unwrap(),
+            range("20220711T000000Z", "20220718T000000Z")
+        );
+    }
+
+    #[test]
+    fn test_range_iso_week_number() {
+        let local = |y, m, d| -> Range {
+            let from = Local.with_ymd_and_hms(y, m, d, 12, 0, 0).unwrap();
+            Range::new(from.into(), None).unwrap()
+        };
+
+        assert_eq!(local(2022, 7, 11).iso_week_number(), 28);
+        assert_eq!(local(2022, 7, 17).iso_week_number(), 28);
+        assert_eq!(local(2021, 1, 3).iso_week_number(), 53);
+        assert_eq!(local(2025, 12, 29).iso_week_number(), 1);
+
+        let entry = TimeEntry::new(local(2022, 1, 1), vec![], None);
+        assert_eq!(entry.iso_week_number(), 52);
+    }
+
+    #[test]
+    fn test_range_pretty_duration() {
+        assert_eq!(Range::pretty_duration(&Duration::seconds(5405)), "01:30:05");
+        assert_eq!(
+            Range::pretty_duration(&Duration::seconds(-5405)),
+            "-01:30:05"
+        );
+        assert_eq!(Range::pretty_duration(&Duration::seconds(-59)), "-00:00:59");
+        assert_eq!(Range::pretty_duration(&Duration::hours(100)), "100:00:00");
+    }
+
+    #[test]
+    fn test_range_split() {
+        let input1: Range = "20220101T120000Z - 20220101T130000Z".parse().unwrap();
+        let output1: Range = "20220101T120000Z - 20220101T123000Z".parse().unwrap();
+        let output2: Range = "20220101T123000Z - 20220101T130000Z".parse().unwrap();
+
+        assert_eq!(input1.split().unwrap(), (output1, output2));
+
+        // Check that 1s duration is not split
+        let input1: Range = "20220101T120000Z - 20220101T120001Z".parse().unwrap();
 
         assert!(input1.split().is_err());
     }
@@ -695,14 +1996,350 @@ mod range_tests {
     }
 }
 
+#[cfg(test)]
+mod work_tests {
+    use crate::data::{Range, TagAttribution, TimeEntry, Work};
+    use chrono::{Duration, FixedOffset, NaiveDate, Utc, Weekday};
+
+    #[test]
+    fn test_work_gaps() {
+        let mut work = Work {
+            entries: vec![],
+            files: None,
+            actions: vec![],
+            range: None,
+        };
+        for range in [
+            "20220711T090000Z - 20220711T100000Z",
+            "20220711T093000Z - 20220711T103000Z",
+            "20220711T140000Z - 20220711T150000Z",
+        ] {
+            work.insert(TimeEntry::new(range.parse().unwrap(), vec![], None));
+        }
+
+        let range: Range = "20220711T080000Z - 20220711T180000Z".parse().unwrap();
+        let excluded: Range = "20220711T120000Z - 20220711T130000Z".parse().unwrap();
+        let gaps: Vec<Range> = [
+            "20220711T080000Z - 20220711T090000Z",
+            "20220711T103000Z - 20220711T120000Z",
+            "20220711T130000Z - 20220711T140000Z",
+            "20220711T150000Z - 20220711T180000Z",
+        ]
+        .iter()
+        .map(|r| r.parse().unwrap())
+        .collect();
+
+        assert_eq!(work.gaps(&range, &[excluded]), gaps);
+
+        let range: Range = "20220711T093000Z - 20220711T100000Z".parse().unwrap();
+        assert!(work.gaps(&range, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_work_duration() {
+        let mut work = Work {
+            entries: vec![],
+            files: None,
+            actions: vec![],
+            range: "20220711T000000Z - 20220712T000000Z".parse().ok(),
+        };
+        for range in [
+            "20220710T220000Z - 20220711T020000Z",
+            "20220711T090000Z - 20220711T100000Z",
+            "20220711T230000Z - 20220712T003000Z",
+        ] {
+            work.insert(TimeEntry::new(range.parse().unwrap(), vec![], None));
+        }
+
+        assert_eq!(work.duration(), Duration::hours(4));
+        assert_eq!(work.unclipped_duration(), Duration::minutes(390));
+        assert_eq!(
+            work.clipped(work.get(1).unwrap()),
+            "20220711T230000Z - 20220712T000000Z".parse().ok()
+        );
+
+        work.range = None;
+        assert_eq!(work.duration(), Duration::minutes(390));
+
+        // An open entry is counted until now, not until the end of the Range
+        let now = Utc::now();
+        let from = now - Duration::hours(2);
+        let entry = TimeEntry::new(Range::new(from, None).unwrap(), vec![], None);
+        work.range = Range::new(now - Duration::hours(1), Some(now + Duration::hours(2))).ok();
+        let clipped = work.clipped(&entry).unwrap();
+        assert_eq!(clipped.from(), now - Duration::hours(1));
+        assert!(clipped.to().unwrap() >= now && clipped.to().unwrap() < now + Duration::minutes(1));
+
+        // The entry is not counted in a Range that has not started yet
+        work.range = Range::new(now + Duration::hours(1), Some(now + Duration::hours(2))).ok();
+        assert_eq!(work.clipped(&entry), None);
+
+        // Nor is it closed in an open Range
+        work.range = Range::new(now - Duration::hours(3), None).ok();
+        assert_eq!(work.clipped(&entry), Some(*entry.range()));
+
+        // An open entry that starts in the future has no time yet
+        let mut work = Work {
+            entries: vec![],
+            files: None,
+            actions: vec![],
+            range: None,
+        };
+        let future = TimeEntry::new(
+            Range::new(now + Duration::hours(1), None).unwrap(),
+            vec![],
+            None,
+        );
+        work.insert(future.clone());
+        assert!(work.entry_parts_in(&future, &Utc).unwrap().is_empty());
+        assert!(work.by_day_in(&Utc).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_work_aggregation() {
+        let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+        let date = |m: u32, d: u32| NaiveDate::from_ymd_opt(2022, m, d).unwrap();
+        let mut work = Work {
+            entries: vec![],
+            files: None,
+            actions: vec![],
+            range: None,
+        };
+        for (range, tags) in [
+            ("20220711T070000Z - 20220711T090000Z", vec!["a", "b"]),
+            ("20220717T200000Z - 20220717T230000Z", vec!["b"]),
+            ("20220731T210000Z - 20220731T230000Z", vec!["a", "b", "c"]),
+            ("20220801T090000Z - 20220801T100000Z", vec![]),
+        ] {
+            let tags = tags.iter().map(|t| t.to_string()).collect();
+            work.insert(TimeEntry::new(range.parse().unwrap(), tags, None));
+        }
+
+        let hours = |h: i64| Duration::hours(h);
+        assert_eq!(
+            work.by_day_in(&tz).unwrap().into_iter().collect::<Vec<_>>(),
+            vec![
+                (date(7, 11), hours(2)),
+                (date(7, 17), hours(2)),
+                (date(7, 18), hours(1)),
+                (date(7, 31), hours(1)),
+                (date(8, 1), hours(2)),
+            ]
+        );
+        assert_eq!(
+            work.by_week_in(Weekday::Mon, &tz)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![
+                (date(7, 11), hours(4)),
+                (date(7, 18), hours(1)),
+                (date(7, 25), hours(1)),
+                (date(8, 1), hours(2)),
+            ]
+        );
+        assert_eq!(
+            work.by_week_in(Weekday::Sun, &tz)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![
+                (date(7, 10), hours(2)),
+                (date(7, 17), hours(3)),
+                (date(7, 31), hours(3)),
+            ]
+        );
+        assert_eq!(
+            work.by_month_in(&tz)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(date(7, 1), hours(6)), (date(8, 1), hours(2))]
+        );
+
+        let tags = |work: &Work, a: TagAttribution| -> Vec<(String, Duration)> {
+            work.by_tag(a).into_iter().collect()
+        };
+        assert_eq!(
+            tags(&work, TagAttribution::Full),
+            vec![
+                ("a".to_string(), hours(4)),
+                ("b".to_string(), hours(7)),
+                ("c".to_string(), hours(2)),
+            ]
+        );
+        assert_eq!(
+            tags(&work, TagAttribution::Split),
+            vec![
+                ("a".to_string(), Duration::minutes(100)),
+                ("b".to_string(), Duration::minutes(280)),
+                ("c".to_string(), Duration::minutes(40)),
+            ]
+        );
+        let split = |work: &Work| {
+            work.by_tag(TagAttribution::Split)
+                .values()
+                .fold(Duration::zero(), |a, d| a + *d)
+        };
+        assert_eq!(split(&work), hours(7));
+
+        // The time that cannot be shared equally goes to the first tag
+        let mut second = Work {
+            entries: vec![],
+            files: None,
+            actions: vec![],
+            range: None,
+        };
+        second.insert(TimeEntry::new(
+            "20220711T070000Z - 20220711T070001Z".parse().unwrap(),
+            ["a", "b", "c"].map(String::from).to_vec(),
+            None,
+        ));
+        let b = second.by_tag(TagAttribution::Split)["b"];
+        assert_eq!(b, Duration::nanoseconds(333_333_333));
+        assert_eq!(
+            second.by_tag(TagAttribution::Split)["a"],
+            Duration::seconds(1) - b * 2
+        );
+        assert_eq!(split(&second), Duration::seconds(1));
+        assert_eq!(
+            tags(&work, TagAttribution::First),
+            vec![("a".to_string(), hours(4)), ("b".to_string(), hours(3))]
+        );
+
+        assert_eq!(
+            work.entry_days_in(work.get(3).unwrap(), &tz).unwrap(),
+            vec![(date(7, 17), hours(2)), (date(7, 18), hours(1))]
+        );
+
+        work.range = "20220717T220000Z - 20220801T000000Z".parse().ok();
+        assert_eq!(
+            work.by_day_in(&tz).unwrap().into_iter().collect::<Vec<_>>(),
+            vec![
+                (date(7, 18), hours(1)),
+                (date(7, 31), hours(1)),
+                (date(8, 1), hours(1)),
+            ]
+        );
+        assert_eq!(
+            tags(&work, TagAttribution::First),
+            vec![("a".to_string(), hours(2)), ("b".to_string(), hours(1))]
+        );
+    }
+}
+
+#[cfg(test)]
+mod store_tests {
+    use crate::data::{ConflictError, TestDir, TimeEntry, Work};
+    use std::fs;
+
+    #[test]
+    fn test_work_store_conflict() {
+        let path = TestDir::new("store");
+        let data = "inc 20220711T080000Z - 20220711T090000Z # a\n";
+        fs::write(path.join("2022-07.data"), data).unwrap();
+
+        let mut work = Work::load_all(&path).unwrap();
+        work.insert(TimeEntry::new(
+            "20220712T080000Z - 20220712T090000Z".parse().unwrap(),
+            vec![],
+            None,
+        ));
+
+        // Another program tracks time in the meantime
+        let concurrent = format!("{}inc 20220713T080000Z - 20220713T090000Z # b\n", data);
+        fs::write(path.join("2022-07.data"), &concurrent).unwrap();
+        let err = work.store(&path).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ConflictError>().unwrap().file(),
+            path.join("2022-07.data")
+        );
+        assert_eq!(
+            fs::read_to_string(path.join("2022-07.data")).unwrap(),
+            concurrent
+        );
+
+        // A new data file is a conflict too
+        let mut work = Work::load_all(&path).unwrap();
+        work.remove(1).unwrap();
+        fs::write(path.join("2022-08.data"), "").unwrap();
+        assert!(work.store(&path).unwrap_err().is::<ConflictError>());
+
+        let mut work = Work::load_all(&path).unwrap();
+        work.remove(1).unwrap();
+        work.store(&path).unwrap();
+        assert_eq!(fs::read_to_string(path.join("2022-07.data")).unwrap(), data);
+
+        // No temporary file is left
+        let mut files: Vec<String> = fs::read_dir(&path)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![".lock", "2022-07.data", "2022-08.data", "undo.data"]
+        );
+    }
+
+    #[test]
+    fn test_work_load_invalid_line() {
+        let path = TestDir::new("load");
+        fs::write(
+            path.join("2022-07.data"),
+            "inc 20220711T080000Z - 20220711T090000Z # a\n\
+             inc 20220712T080000Z - 20220712T090000Z # b # \"unterminated\n",
+        )
+        .unwrap();
+
+        let err = Work::load_all(&path).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!("Invalid entry at {}:2", path.join("2022-07.data").display())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tagsdata_tests {
+    use crate::data::{TagsData, TestDir};
+    use std::fs;
+
+    #[test]
+    fn test_tagsdata_load() {
+        let path = TestDir::new("tags");
+
+        // Missing file
+        assert_eq!(TagsData::load(&path).unwrap(), TagsData::default());
+
+        let data = "{\n  \"a\":{\"count\":3},\n  \"b \\\"c\\\"\":{\"count\":1}\n}";
+        fs::write(path.join("tags.data"), data).unwrap();
+        let mut tags = TagsData::load(&path).unwrap();
+        assert_eq!(tags.count("a"), 3);
+        assert_eq!(tags.count("b \"c\""), 1);
+        assert_eq!(tags.count("d"), 0);
+        assert_eq!(tags.serialize(), data);
+
+        tags.add("a", -4);
+        tags.add("d", 1);
+        assert_eq!(
+            tags.tags().collect::<Vec<_>>(),
+            vec![("a", 0), ("b \"c\"", 1), ("d", 1)]
+        );
+
+        fs::write(path.join("tags.data"), "{\"a\":{\"count\":3}").unwrap();
+        assert!(TagsData::load(&path).is_err());
+    }
+}
+
 #[cfg(test)]
 mod timeentry_tests {
-    use crate::data::TimeEntry;
-    use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+    use crate::data::{parse_line, TimeEntry};
+    use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
 
     fn parse_date_time(date: &str) -> DateTime<Utc> {
         let d = NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ").unwrap();
-        DateTime::<Utc>::from_utc(d, Utc)
+        Utc.from_utc_datetime(&d)
     }
 
     #[test]
@@ -722,5 +2359,74 @@ mod timeentry_tests {
         assert_eq!(input1.range().from, parse_date_time("20220101T120000Z"));
         assert_eq!(input1.tags(), vec!["tag1", "tag 2  ", " t a g 3 "]);
         assert!(input1.range().is_open());
+
+        let input1: TimeEntry = "inc 20220101T120000Z - 20220101T124500Z # tag1 # \"a \\\"b\\\"\""
+            .parse()
+            .unwrap();
+
+        assert_eq!(input1.tags(), vec!["tag1"]);
+        assert_eq!(input1.annotation(), Some("a \"b\""));
+
+        let input1: TimeEntry = "inc 20220101T120000Z - 20220101T124500Z # # \"note\""
+            .parse()
+            .unwrap();
+
+        assert!(input1.tags().is_empty());
+        assert_eq!(input1.annotation(), Some("note"));
+
+        let input1: TimeEntry = "inc 20220101T120000Z - 20220101T124500Z".parse().unwrap();
+
+        assert!(input1.tags().is_empty());
+        assert_eq!(input1.annotation(), None);
+    }
+
+    #[test]
+    fn test_timeentry_serialize() {
+        for line in [
+            "inc 20220101T120000Z - 20220101T124500Z # tag1 \"tag 2\" \"tag-3\"",
+            "inc 20220101T120000Z # tag1 # \"a \\\"b\\\"\"",
+            "inc 20220101T120000Z - 20220101T124500Z # # \"note\"",
+            "inc 20220101T120000Z - 20220101T124500Z",
+        ] {
+            assert_eq!(line.parse::<TimeEntry>().unwrap().serialize(), line);
+        }
+
+        let entry = TimeEntry::new(
+            "20220101T120000Z - 20220101T124500Z".parse().unwrap(),
+            vec!["a\nb".to_string()],
+            Some("line 1\r\nline 2 \\n".to_string()),
+        );
+        let line = entry.serialize();
+        assert_eq!(
+            line,
+            "inc 20220101T120000Z - 20220101T124500Z # \"a\\nb\" # \"line 1\\r\\nline 2 \\\\n\""
+        );
+        let parsed = parse_line(&line).unwrap();
+        assert_eq!(parsed.tags(), entry.tags());
+        assert_eq!(parsed.annotation(), entry.annotation());
+
+        // A bare `#` separates the tags from the annotation, so tags with a `#` are quoted
+        let entry = TimeEntry::new(
+            "20220101T120000Z".parse().unwrap(),
+            vec!["#".to_string(), "bug#1".to_string()],
+            Some("#".to_string()),
+        );
+        let line = entry.serialize();
+        assert_eq!(line, "inc 20220101T120000Z # \"#\" \"bug#1\" # \"#\"");
+        let parsed = parse_line(&line).unwrap();
+        assert_eq!(parsed.tags(), entry.tags());
+        assert_eq!(parsed.annotation(), entry.annotation());
+
+        // The JSON of undo.data escapes all the control characters
+        let entry = TimeEntry::new(
+            "20220101T120000Z".parse().unwrap(),
+            vec!["a\tb".to_string(), "c\u{1b}[0m".to_string()],
+            Some("\"line 1\"\r\nline 2\u{7f}".to_string()),
+        );
+        assert_eq!(
+            entry.json(),
+            "{\"start\":\"20220101T120000Z\",\"tags\":[\"a\\tb\",\"c\\u001b[0m\"],\
+             \"annotation\":\"\\\"line 1\\\"\\r\\nline 2\\u007f\"}"
+        );
     }
 }
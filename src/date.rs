@@ -0,0 +1,487 @@
+// This file contains the parsers of the date/time and interval expressions accepted by timew,
+// like `9am`, `yesterday`, `sow`, `2h ago` or `from 9am to 5pm`.
+
+use anyhow::{bail, Result};
+use chrono::{
+    DateTime, Datelike, Duration, Local, LocalResult, Months, NaiveDate, NaiveDateTime, NaiveTime,
+    TimeZone, Utc, Weekday,
+};
+use regex::Regex;
+
+use crate::data::Range;
+use crate::duration::parse_duration;
+
+/// Parse a date/time expression of timew, in local time and with weeks starting on `weekstart`.
+/// See `parse_date_time_at`.
+pub fn parse_date_time(text: &str, weekstart: Weekday) -> Result<DateTime<Utc>> {
+    parse_date_time_at(text, &Local::now(), weekstart)
+}
+
+/// Parse a date/time expression of timew. Relative expressions are computed from `now`, and dates
+/// and times without an offset are in the timezone of `now`. Expressions can be:
+///  - `now`
+///  - a time: `9am`, `9:30pm`, `14:00`, `14:00:30`, today at that time
+///  - a day: `today`, `yesterday`, `tomorrow`, a weekday such as `monday` or `mon`, the last such
+///    day, today included, or an ordinal such as `1st` or `23rd`, the last such day of a month,
+///    today included. Days start at midnight.
+///  - the start or end of the current, previous or next day, week, month, quarter or year: `sod`,
+///    `eod`, `sow`, `eow`, `som`, `eom`, `soq`, `eoq`, `soy`, `eoy`, and `sopw`, `eopw`, `sonw`,
+///    `eonw`... The end of a period is the start of the next one. Weeks start on `weekstart`.
+///  - a day followed by a time: `yesterday 9am`, `monday 14:00`, `2022-07-11 9:00`
+///  - a duration ago: `2h ago`, `30min ago`, `3 days ago`
+///  - an ISO 8601 date or date/time: `2022-07-11`, `2022-07-11T09:00`, `20220711T090000`, in
+///    the timezone of `now` unless they have an offset, as in `20220711T090000Z` or
+///    `2022-07-11T09:00:00+02:00`.
+pub fn parse_date_time_at<Tz: TimeZone>(
+    text: &str,
+    now: &DateTime<Tz>,
+    weekstart: Weekday,
+) -> Result<DateTime<Utc>> {
+    let text = text.trim();
+    let lower = text.to_lowercase();
+    let tz = now.timezone();
+    let today = now.date_naive();
+
+    if let Some(t) = parse_iso_date_time(text, &tz)? {
+        return Ok(t);
+    }
+
+    if lower == "now" {
+        return Ok(now.with_timezone(&Utc));
+    }
+
+    if let Some(duration) = lower.strip_suffix(" ago") {
+        return shift(now.with_timezone(&Utc), -parse_duration(duration.trim())?);
+    }
+
+    if let Some(day) = parse_day(&lower, today, weekstart) {
+        return at(&tz, day, NaiveTime::MIN);
+    }
+
+    if let Some(time) = parse_time(&lower) {
+        return at(&tz, today, time);
+    }
+
+    if let Some((day, time)) = lower.split_once(char::is_whitespace) {
+        if let (Some(day), Some(time)) = (parse_day(day, today, weekstart), parse_time(time.trim()))
+        {
+            return at(&tz, day, time);
+        }
+    }
+
+    bail!("'{}' is not a valid date/time.", text)
+}
+
+/// Parse an interval expression of timew, in local time and with weeks starting on `weekstart`.
+/// See `parse_interval_at`.
+pub fn parse_interval(text: &str, weekstart: Weekday) -> Result<Range> {
+    parse_interval_at(text, &Local::now(), weekstart)
+}
+
+/// Parse an interval expression of timew, where dates are date/time expressions parsed by
+/// `parse_date_time_at` and durations are parsed by `duration::parse_duration`. Intervals can be:
+///  - `[from] <date>`: an open Range starting at the date, like `2h ago`
+///  - `[from] <date> to <date>` or `[from] <date> - <date>`
+///  - `[from] <date> for <duration>`
+///  - `<duration> before <date>` and `<duration> after <date>`
+///  - `[for] <duration>`: the Range of that duration ending now
+pub fn parse_interval_at<Tz: TimeZone>(
+    text: &str,
+    now: &DateTime<Tz>,
+    weekstart: Weekday,
+) -> Result<Range> {
+    let lower = text.trim().to_lowercase();
+    let text = lower.strip_prefix("from ").unwrap_or(&lower).trim();
+    let date = |t: &str| parse_date_time_at(t, now, weekstart);
+
+    if let Some((from, to)) = text.split_once(" to ").or_else(|| text.split_once(" - ")) {
+        return Range::new(date(from)?, Some(date(to)?));
+    }
+
+    if let Some((from, duration)) = text.split_once(" for ") {
+        let from = date(from)?;
+        return Range::new(from, Some(shift(from, parse_duration(duration.trim())?)?));
+    }
+
+    if let Some((duration, to)) = text.split_once(" before ") {
+        let to = date(to)?;
+        return Range::new(shift(to, -parse_duration(duration.trim())?)?, Some(to));
+    }
+
+    if let Some((duration, from)) = text.split_once(" after ") {
+        let from = date(from)?;
+        return Range::new(from, Some(shift(from, parse_duration(duration.trim())?)?));
+    }
+
+    if let Ok(duration) = parse_duration(text.strip_prefix("for ").unwrap_or(text).trim()) {
+        let to = now.with_timezone(&Utc);
+        return Range::new(shift(to, -duration)?, Some(to));
+    }
+
+    Range::new(date(text)?, None)
+}
+
+// Return the date/time moved by the duration, failing when it is out of the supported dates.
+fn shift(time: DateTime<Utc>, duration: Duration) -> Result<DateTime<Utc>> {
+    match time.checked_add_signed(duration) {
+        Some(t) => Ok(t),
+        None => bail!("The date is out of range."),
+    }
+}
+
+// Return the date/time at the given local date and time in the timezone. A local time that falls
+// in a DST gap is moved forward by one hour, and an ambiguous one is the earliest.
+pub(crate) fn local_date_time<Tz: TimeZone>(tz: &Tz, time: NaiveDateTime) -> Result<DateTime<Tz>> {
+    match tz.from_local_datetime(&time) {
+        LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => Ok(t),
+        LocalResult::None => match tz.from_local_datetime(&(time + Duration::hours(1))) {
+            LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => Ok(t),
+            LocalResult::None => bail!("Cannot determine the local time {}", time),
+        },
+    }
+}
+
+fn at<Tz: TimeZone>(tz: &Tz, day: NaiveDate, time: NaiveTime) -> Result<DateTime<Utc>> {
+    Ok(local_date_time(tz, day.and_time(time))?.with_timezone(&Utc))
+}
+
+// Parse the date/time formats of ISO 8601, that do not depend on the current date.
+fn parse_iso_date_time<Tz: TimeZone>(text: &str, tz: &Tz) -> Result<Option<DateTime<Utc>>> {
+    let text = text.to_uppercase();
+
+    for format in ["%Y%m%dT%H%M%SZ", "%Y-%m-%dT%H:%M:%SZ", "%Y-%m-%dT%H:%MZ"] {
+        if let Ok(t) = NaiveDateTime::parse_from_str(&text, format) {
+            return Ok(Some(Utc.from_utc_datetime(&t)));
+        }
+    }
+
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%:z",
+        "%Y-%m-%dT%H:%M%:z",
+        "%Y%m%dT%H%M%S%z",
+    ] {
+        if let Ok(t) = DateTime::parse_from_str(&text, format) {
+            return Ok(Some(t.with_timezone(&Utc)));
+        }
+    }
+
+    for format in ["%Y%m%dT%H%M%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(t) = NaiveDateTime::parse_from_str(&text, format) {
+            return Ok(Some(local_date_time(tz, t)?.with_timezone(&Utc)));
+        }
+    }
+
+    Ok(None)
+}
+
+pub(crate) const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("monday", Weekday::Mon),
+    ("tuesday", Weekday::Tue),
+    ("wednesday", Weekday::Wed),
+    ("thursday", Weekday::Thu),
+    ("friday", Weekday::Fri),
+    ("saturday", Weekday::Sat),
+    ("sunday", Weekday::Sun),
+];
+
+// Return the last day that is the given weekday, today included.
+pub(crate) fn last_weekday(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days = (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+    today - Duration::days(days.into())
+}
+
+// Parse an expression designating a day, relative to today.
+fn parse_day(text: &str, today: NaiveDate, weekstart: Weekday) -> Option<NaiveDate> {
+    match text {
+        "today" => return Some(today),
+        "yesterday" => return Some(today - Duration::days(1)),
+        "tomorrow" => return Some(today + Duration::days(1)),
+        _ => {}
+    }
+
+    for (name, weekday) in WEEKDAYS {
+        if text == name || text == &name[..3] {
+            return Some(last_weekday(today, weekday));
+        }
+    }
+
+    let period_re = Regex::new(r"^([se])o([pn]?)([dwmqy])$").unwrap();
+    if let Some(c) = period_re.captures(text) {
+        let offset = match (&c[1], &c[2]) {
+            ("s", "") | ("e", "p") => 0,
+            ("s", "p") => -1,
+            ("s", "n") | ("e", "") => 1,
+            _ => 2,
+        };
+        return period_start(today, c[3].chars().next().unwrap(), offset, weekstart);
+    }
+
+    let ordinal_re = Regex::new(r"^(\d{1,2})(st|nd|rd|th)$").unwrap();
+    if let Some(c) = ordinal_re.captures(text) {
+        let day: u32 = c[1].parse().ok()?;
+        let mut month = today.with_day(1)?;
+        // Going back a year is enough to find any day between the 1st and the 31st.
+        for _ in 0..12 {
+            match month.with_day(day) {
+                Some(d) if d <= today => return Some(d),
+                _ => month = month.checked_sub_months(Months::new(1))?,
+            }
+        }
+        return None;
+    }
+
+    ["%Y-%m-%d", "%Y%m%d"]
+        .iter()
+        .find_map(|f| NaiveDate::parse_from_str(text, f).ok())
+}
+
+// Return the first day of the period of the given kind (day, week, month, quarter or year) that is
+// `offset` periods after the one containing the given day. Weeks start on `weekstart`.
+pub(crate) fn period_start(
+    day: NaiveDate,
+    kind: char,
+    offset: i32,
+    weekstart: Weekday,
+) -> Option<NaiveDate> {
+    let months = |first: NaiveDate, n: i32| match n {
+        n if n < 0 => first.checked_sub_months(Months::new(n.unsigned_abs())),
+        n => first.checked_add_months(Months::new(n as u32)),
+    };
+
+    match kind {
+        'd' => Some(day + Duration::days(offset.into())),
+        'w' => Some(last_weekday(day, weekstart) + Duration::weeks(offset.into())),
+        'm' => months(day.with_day(1)?, offset),
+        'q' => months(
+            day.with_day(1)?.with_month0(day.month0() / 3 * 3)?,
+            offset * 3,
+        ),
+        'y' => NaiveDate::from_ymd_opt(day.year() + offset, 1, 1),
+        _ => None,
+    }
+}
+
+// Parse a time of the day: `9am`, `9:30pm`, `14:00` or `14:00:30`.
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    let re = Regex::new(r"^(\d{1,2})(?::(\d{2}))?(?::(\d{2}))?\s*(am|pm)?$").unwrap();
+    let c = re.captures(text)?;
+    let mut hour: u32 = c[1].parse().ok()?;
+    let min = c.get(2).map_or(Some(0), |m| m.as_str().parse().ok())?;
+    let sec = c.get(3).map_or(Some(0), |s| s.as_str().parse().ok())?;
+
+    match c.get(4).map(|m| m.as_str()) {
+        // A bare number is not a time.
+        None if c.get(2).is_none() => return None,
+        None => {}
+        Some(_) if hour == 0 || hour > 12 => return None,
+        Some("am") => hour %= 12,
+        Some(_) => hour = hour % 12 + 12,
+    }
+
+    NaiveTime::from_hms_opt(hour, min, sec)
+}
+
+#[cfg(test)]
+mod date_tests {
+    use crate::data::Range;
+    use crate::date::{parse_date_time_at, parse_interval_at};
+    use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone, Utc, Weekday};
+
+    fn parse_date_time(date: &str) -> DateTime<Utc> {
+        let d = NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ").unwrap();
+        Utc.from_utc_datetime(&d)
+    }
+
+    // Wednesday 13 July 2022, 15:30:20 at UTC+02:00
+    fn now() -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(2 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2022, 7, 13, 15, 30, 20)
+            .unwrap()
+    }
+
+    fn parse(text: &str) -> DateTime<Utc> {
+        parse_date_time_at(text, &now(), Weekday::Mon).unwrap()
+    }
+
+    #[test]
+    fn test_parse_times() {
+        assert_eq!(parse("now"), parse_date_time("20220713T133020Z"));
+        assert_eq!(parse("9am"), parse_date_time("20220713T070000Z"));
+        assert_eq!(parse("9:30PM"), parse_date_time("20220713T193000Z"));
+        assert_eq!(parse("12am"), parse_date_time("20220712T220000Z"));
+        assert_eq!(parse("12pm"), parse_date_time("20220713T100000Z"));
+        assert_eq!(parse("14:05"), parse_date_time("20220713T120500Z"));
+        assert_eq!(parse("14:05:30"), parse_date_time("20220713T120530Z"));
+        assert_eq!(parse("2h ago"), parse_date_time("20220713T113020Z"));
+        assert_eq!(parse("1.5h ago"), parse_date_time("20220713T120020Z"));
+        assert_eq!(parse("3 days ago"), parse_date_time("20220710T133020Z"));
+        assert_eq!(parse("yesterday 9am"), parse_date_time("20220712T070000Z"));
+        assert_eq!(parse("mon 14:00"), parse_date_time("20220711T120000Z"));
+        assert_eq!(
+            parse("2022-07-01 8:00"),
+            parse_date_time("20220701T060000Z")
+        );
+
+        for invalid in [
+            "9",
+            "13pm",
+            "0am",
+            "25:00",
+            "9:60",
+            "2 ago",
+            "2parsecs ago",
+            "later",
+            "10000000000h ago",
+        ] {
+            assert!(
+                parse_date_time_at(invalid, &now(), Weekday::Mon).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_days() {
+        assert_eq!(parse("today"), parse_date_time("20220712T220000Z"));
+        assert_eq!(parse("yesterday"), parse_date_time("20220711T220000Z"));
+        assert_eq!(parse("tomorrow"), parse_date_time("20220713T220000Z"));
+        assert_eq!(parse("monday"), parse_date_time("20220710T220000Z"));
+        assert_eq!(parse("Wednesday"), parse_date_time("20220712T220000Z"));
+        assert_eq!(parse("thu"), parse_date_time("20220706T220000Z"));
+        assert_eq!(parse("1st"), parse_date_time("20220630T220000Z"));
+        assert_eq!(parse("13th"), parse_date_time("20220712T220000Z"));
+        assert_eq!(parse("22nd"), parse_date_time("20220621T220000Z"));
+        assert_eq!(parse("31st"), parse_date_time("20220530T220000Z"));
+        assert!(parse_date_time_at("32nd", &now(), Weekday::Mon).is_err());
+    }
+
+    #[test]
+    fn test_parse_periods() {
+        assert_eq!(parse("sod"), parse_date_time("20220712T220000Z"));
+        assert_eq!(parse("eod"), parse_date_time("20220713T220000Z"));
+        assert_eq!(parse("sopd"), parse_date_time("20220711T220000Z"));
+        assert_eq!(parse("sow"), parse_date_time("20220710T220000Z"));
+        assert_eq!(parse("eow"), parse_date_time("20220717T220000Z"));
+        assert_eq!(parse("sopw"), parse_date_time("20220703T220000Z"));
+        assert_eq!(parse("eopw"), parse_date_time("20220710T220000Z"));
+        assert_eq!(parse("sonw"), parse_date_time("20220717T220000Z"));
+        assert_eq!(parse("eonw"), parse_date_time("20220724T220000Z"));
+        assert_eq!(parse("som"), parse_date_time("20220630T220000Z"));
+        assert_eq!(parse("eom"), parse_date_time("20220731T220000Z"));
+        assert_eq!(parse("sopm"), parse_date_time("20220531T220000Z"));
+        assert_eq!(parse("soq"), parse_date_time("20220630T220000Z"));
+        assert_eq!(parse("sopq"), parse_date_time("20220331T220000Z"));
+        assert_eq!(parse("eoq"), parse_date_time("20220930T220000Z"));
+        assert_eq!(parse("soy"), parse_date_time("20211231T220000Z"));
+        assert_eq!(parse("eoy"), parse_date_time("20221231T220000Z"));
+        assert_eq!(parse("sopy"), parse_date_time("20201231T220000Z"));
+
+        let sunday = |text: &str| parse_date_time_at(text, &now(), Weekday::Sun).unwrap();
+        assert_eq!(sunday("sow"), parse_date_time("20220709T220000Z"));
+        assert_eq!(sunday("eow"), parse_date_time("20220716T220000Z"));
+        assert_eq!(sunday("sopw"), parse_date_time("20220702T220000Z"));
+        assert_eq!(sunday("monday"), parse_date_time("20220710T220000Z"));
+    }
+
+    #[test]
+    fn test_parse_iso() {
+        assert_eq!(
+            parse("20220711T090000Z"),
+            parse_date_time("20220711T090000Z")
+        );
+        assert_eq!(
+            parse("2022-07-11T09:00:00Z"),
+            parse_date_time("20220711T090000Z")
+        );
+        assert_eq!(
+            parse("2022-07-11T09:00Z"),
+            parse_date_time("20220711T090000Z")
+        );
+        assert_eq!(
+            parse("2022-07-11T09:00:00-05:00"),
+            parse_date_time("20220711T140000Z")
+        );
+        assert_eq!(
+            parse("2022-07-11T09:00"),
+            parse_date_time("20220711T070000Z")
+        );
+        assert_eq!(
+            parse("2022-07-11T09:00:10"),
+            parse_date_time("20220711T070010Z")
+        );
+        assert_eq!(
+            parse("20220711T090000"),
+            parse_date_time("20220711T070000Z")
+        );
+        assert_eq!(parse("2022-07-11"), parse_date_time("20220710T220000Z"));
+        assert_eq!(parse("20220711"), parse_date_time("20220710T220000Z"));
+        assert!(parse_date_time_at("2022-13-01", &now(), Weekday::Mon).is_err());
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        let interval = |text: &str| parse_interval_at(text, &now(), Weekday::Mon).unwrap();
+        let range = |from: &str, to: Option<&str>| {
+            Range::new(parse_date_time(from), to.map(parse_date_time)).unwrap()
+        };
+
+        assert_eq!(
+            interval("from 9am to 11:30"),
+            range("20220713T070000Z", Some("20220713T093000Z"))
+        );
+        assert_eq!(
+            interval("yesterday 9am - yesterday 5pm"),
+            range("20220712T070000Z", Some("20220712T150000Z"))
+        );
+        assert_eq!(
+            interval("2022-07-11T09:00 - 2022-07-11T10:00"),
+            range("20220711T070000Z", Some("20220711T080000Z"))
+        );
+        assert_eq!(
+            interval("from monday 9am for 1.5h"),
+            range("20220711T070000Z", Some("20220711T083000Z"))
+        );
+        assert_eq!(
+            interval("30min before 2pm"),
+            range("20220713T113000Z", Some("20220713T120000Z"))
+        );
+        assert_eq!(
+            interval("2h after sod"),
+            range("20220712T220000Z", Some("20220713T000000Z"))
+        );
+        assert_eq!(
+            interval("for 2h"),
+            range("20220713T113020Z", Some("20220713T133020Z"))
+        );
+        assert_eq!(
+            interval("45min"),
+            range("20220713T124520Z", Some("20220713T133020Z"))
+        );
+        assert_eq!(interval("2h ago"), range("20220713T113020Z", None));
+        assert_eq!(interval("from 9am"), range("20220713T070000Z", None));
+        assert_eq!(
+            interval("20220711T090000Z"),
+            range("20220711T090000Z", None)
+        );
+
+        for invalid in [
+            "5pm - 9am",
+            "9am for 2",
+            "9am to",
+            "2h before",
+            "someday",
+            "9am for 10000000000h",
+            "10000000000h before 9am",
+            "10000000000h after 9am",
+            "for 10000000000h",
+        ] {
+            assert!(
+                parse_interval_at(invalid, &now(), Weekday::Mon).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
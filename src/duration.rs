@@ -0,0 +1,244 @@
+// This file contains the functions that parse and format durations, like `2h`, `PT1H30M` or
+// `1:30:00`.
+
+use anyhow::{bail, Result};
+use chrono::Duration;
+use regex::Regex;
+
+/// The formats of `format_duration`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DurationFormat {
+    /// ISO 8601, in hours, minutes and seconds: `PT1H30M`
+    Iso,
+    /// Hours, with 2 decimals: `1.50`
+    DecimalHours,
+    /// Hours, minutes and seconds that are not zero: `1h 30m`
+    Human,
+    /// Hours, minutes and seconds, like a clock: `1:30:00`
+    Clock,
+}
+
+/// Parse a duration. A duration is either in ISO 8601 format, such as `PT1H30M` or `P2D`, or made
+/// of numbers each followed by a unit: `2h`, `90min`, `1.5h`, `3days`, `1h 30m`. Units are
+/// seconds (`s`, `sec`, `second`...), minutes (`m`, `min`, `minute`...), hours (`h`, `hr`,
+/// `hour`...), days (`d`, `day`...) and weeks (`w`, `wk`, `week`...). A duration can be negative,
+/// with a leading `-`. Months and years are not accepted, as their duration varies.
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let trimmed = text.trim();
+    let (negative, value) = match trimmed.strip_prefix('-') {
+        Some(v) => (true, v.trim_start()),
+        None => (false, trimmed),
+    };
+
+    let total = match value.strip_prefix(['P', 'p']) {
+        Some(iso) => parse_iso(iso),
+        None => parse_units(value),
+    };
+    let duration = match total {
+        Some(total) => seconds(total)?,
+        None => bail!("'{}' is not a valid duration.", text),
+    };
+
+    Ok(if negative { -duration } else { duration })
+}
+
+// Return the number of seconds of the unit.
+fn unit_seconds(unit: &str) -> Option<f64> {
+    match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(1.0),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(60.0),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(3600.0),
+        "d" | "day" | "days" => Some(86400.0),
+        "w" | "wk" | "wks" | "week" | "weeks" => Some(604800.0),
+        _ => None,
+    }
+}
+
+// Return the duration of the given number of seconds, rounded to the millisecond. Casting a float
+// to an integer saturates, so the values out of the range of a Duration are checked first.
+fn seconds(value: f64) -> Result<Duration> {
+    let milliseconds = (value * 1000.0).round();
+    match Some(milliseconds)
+        .filter(|m| m.is_finite() && m.abs() < i64::MAX as f64)
+        .and_then(|m| Duration::try_milliseconds(m as i64))
+    {
+        Some(d) => Ok(d),
+        None => bail!("The duration of {} seconds is too long.", value),
+    }
+}
+
+// Parse numbers followed by units, such as `1h 30min`, and return the number of seconds.
+fn parse_units(text: &str) -> Option<f64> {
+    let re = Regex::new(r"^\s*(\d+(?:\.\d+)?|\.\d+)\s*([a-zA-Z]+)").unwrap();
+    if text.is_empty() {
+        return None;
+    }
+
+    let mut rest = text;
+    let mut total = 0.0;
+    while !rest.is_empty() {
+        let c = re.captures(rest)?;
+        total += c[1].parse::<f64>().ok()? * unit_seconds(&c[2].to_lowercase())?;
+        rest = rest[c[0].len()..].trim_start();
+    }
+
+    Some(total)
+}
+
+// Parse an ISO 8601 duration without its leading `P`: `1W`, `2DT3H`, `T1H30M`, `T0.5H`, and
+// return the number of seconds.
+fn parse_iso(text: &str) -> Option<f64> {
+    let re = Regex::new(
+        r"^(?:(\d+(?:\.\d+)?)W)?(?:(\d+(?:\.\d+)?)D)?(?:T(?:(\d+(?:\.\d+)?)H)?(?:(\d+(?:\.\d+)?)M)?(?:(\d+(?:\.\d+)?)S)?)?$",
+    )
+    .unwrap();
+    let text = text.to_uppercase();
+    if text.is_empty() || text.ends_with('T') {
+        return None;
+    }
+    let c = re.captures(&text)?;
+
+    let mut total = 0.0;
+    for (i, unit) in [
+        (1, 604800.0),
+        (2, 86400.0),
+        (3, 3600.0),
+        (4, 60.0),
+        (5, 1.0),
+    ] {
+        if let Some(m) = c.get(i) {
+            total += m.as_str().parse::<f64>().ok()? * unit;
+        }
+    }
+
+    Some(total)
+}
+
+/// Format the duration in the given format. Negative durations start with a `-`, and fractions of
+/// seconds are dropped, so durations under a second are formatted as zero, without sign.
+pub fn format_duration(duration: &Duration, format: DurationFormat) -> String {
+    let sign = if duration.num_seconds() < 0 { "-" } else { "" };
+    let total = duration.num_seconds().unsigned_abs();
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+
+    match format {
+        DurationFormat::Iso => {
+            let mut iso = String::from("PT");
+            if hours > 0 {
+                iso.push_str(&format!("{}H", hours));
+            }
+            if minutes > 0 {
+                iso.push_str(&format!("{}M", minutes));
+            }
+            if seconds > 0 || total == 0 {
+                iso.push_str(&format!("{}S", seconds));
+            }
+            format!("{}{}", sign, iso)
+        }
+        DurationFormat::DecimalHours => {
+            format!("{}{:.2}", sign, total as f64 / 3600.0)
+        }
+        DurationFormat::Human => {
+            let parts: Vec<String> = [(hours, "h"), (minutes, "m"), (seconds, "s")]
+                .iter()
+                .filter(|(value, _)| *value > 0)
+                .map(|(value, unit)| format!("{}{}", value, unit))
+                .collect();
+            if parts.is_empty() {
+                return "0s".to_string();
+            }
+            format!("{}{}", sign, parts.join(" "))
+        }
+        DurationFormat::Clock => {
+            format!("{}{}:{:02}:{:02}", sign, hours, minutes, seconds)
+        }
+    }
+}
+
+#[cfg(test)]
+mod duration_tests {
+    use crate::duration::{format_duration, parse_duration, DurationFormat};
+    use chrono::Duration;
+
+    #[test]
+    fn test_parse_duration() {
+        let minutes = |m: i64| Duration::minutes(m);
+
+        assert_eq!(parse_duration("2h").unwrap(), minutes(120));
+        assert_eq!(parse_duration("90min").unwrap(), minutes(90));
+        assert_eq!(parse_duration("1.5h").unwrap(), minutes(90));
+        assert_eq!(parse_duration("1.5 hours").unwrap(), minutes(90));
+        assert_eq!(parse_duration("1h30m").unwrap(), minutes(90));
+        assert_eq!(
+            parse_duration("1h 30min 15s").unwrap(),
+            Duration::seconds(5415)
+        );
+        assert_eq!(parse_duration("3days").unwrap(), Duration::days(3));
+        assert_eq!(parse_duration("2 weeks").unwrap(), Duration::weeks(2));
+        assert_eq!(parse_duration("-45min").unwrap(), minutes(-45));
+        assert_eq!(parse_duration("PT1H30M").unwrap(), minutes(90));
+        assert_eq!(parse_duration("pt0.5h").unwrap(), minutes(30));
+        assert_eq!(parse_duration("P2DT3H").unwrap(), Duration::hours(51));
+        assert_eq!(parse_duration("P1W").unwrap(), Duration::weeks(1));
+        assert_eq!(parse_duration("PT20S").unwrap(), Duration::seconds(20));
+        assert_eq!(parse_duration("-PT1M").unwrap(), minutes(-1));
+
+        for invalid in [
+            "",
+            "2",
+            "h",
+            "2 parsecs",
+            "1h30",
+            "P",
+            "PT",
+            "P1M",
+            "P1Y",
+            "PT1H2D",
+            "10000000000000h",
+            "PT10000000000000H",
+        ] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        let formats = |d: Duration| {
+            [
+                DurationFormat::Iso,
+                DurationFormat::DecimalHours,
+                DurationFormat::Human,
+                DurationFormat::Clock,
+            ]
+            .map(|f| format_duration(&d, f))
+        };
+
+        assert_eq!(
+            formats(Duration::seconds(5400)),
+            ["PT1H30M", "1.50", "1h 30m", "1:30:00"]
+        );
+        assert_eq!(
+            formats(Duration::seconds(-5405)),
+            ["-PT1H30M5S", "-1.50", "-1h 30m 5s", "-1:30:05"]
+        );
+        assert_eq!(
+            formats(Duration::seconds(-30)),
+            ["-PT30S", "-0.01", "-30s", "-0:00:30"]
+        );
+        assert_eq!(formats(Duration::zero()), ["PT0S", "0.00", "0s", "0:00:00"]);
+        assert_eq!(
+            formats(Duration::milliseconds(-500)),
+            ["PT0S", "0.00", "0s", "0:00:00"]
+        );
+        assert_eq!(
+            formats(Duration::hours(26)),
+            ["PT26H", "26.00", "26h", "26:00:00"]
+        );
+
+        for d in [Duration::seconds(5415), Duration::seconds(-61)] {
+            for f in [DurationFormat::Iso, DurationFormat::Human] {
+                assert_eq!(parse_duration(&format_duration(&d, f)).unwrap(), d);
+            }
+        }
+    }
+}
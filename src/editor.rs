@@ -1,9 +1,1133 @@
 // This will contain all functions that edit the time entries (start, stop, split, remove, ...)
 
-use crate::data::TimeEntry;
+use crate::config;
+use crate::config::{Config, Exclusions};
+use crate::data::{local_midnight, Range, TimeEntry, Work};
+use crate::filter::Filter;
+use crate::rangeset::RangeSet;
 use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Local, Utc};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 
-// Stop the current tracking, start a new tracking with the new tags and return the time entry
+fn exclusions() -> Result<Exclusions> {
+    Config::load(&config::config_path()?)?.exclusions()
+}
+
+fn to_tags(tags: &[&str]) -> Vec<String> {
+    tags.iter().map(|t| t.to_string()).collect()
+}
+
+// Stop the open entry, if any, at `now` and add a new open entry with the given tags.
+fn start_entry(
+    work: &mut Work,
+    now: DateTime<Utc>,
+    tags: &[&str],
+    fill: Option<&Exclusions>,
+) -> Result<TimeEntry> {
+    if let Some(open) = work.get(1).filter(|e| e.range().is_open()).cloned() {
+        let stopped = Range::new(open.range().from(), Some(now))?;
+        work.replace(
+            1,
+            TimeEntry::new(
+                stopped,
+                open.tags().to_vec(),
+                open.annotation().map(String::from),
+            ),
+        )?;
+    }
+
+    work.insert(TimeEntry::new(Range::new(now, None)?, to_tags(tags), None));
+
+    match fill {
+        Some(exclusions) => fill_entry(work, 1, exclusions),
+        None => Ok(work.get(1).unwrap().clone()),
+    }
+}
+
+// Fail if the Range overlaps an entry, other than the one with the `except` id.
+fn ensure_no_overlap(work: &Work, range: &Range, except: Option<usize>) -> Result<()> {
+    let tracked: RangeSet = work
+        .entries()
+        .iter()
+        .filter(|e| Some(e.id()) != except)
+        .map(|e| *e.range())
+        .collect();
+    if !tracked.intersection(&RangeSet::from(range)).is_empty() {
+        bail!("You cannot overlap intervals. Correct the start/end time.");
+    }
+
+    Ok(())
+}
+
+// Add a new closed entry, that must not overlap any existing entry.
+fn track_entry(
+    work: &mut Work,
+    range: Range,
+    tags: &[&str],
+    fill: Option<&Exclusions>,
+) -> Result<TimeEntry> {
+    if range.is_open() {
+        bail!("The range to track must have an end");
+    }
+    ensure_no_overlap(work, &range, None)?;
+
+    work.insert(TimeEntry::new(range, to_tags(tags), None));
+    let entry = inserted(work, &range);
+
+    match fill {
+        Some(exclusions) => fill_entry(work, entry.id(), exclusions),
+        None => Ok(entry),
+    }
+}
+
+// Extend the entry with the given id to the gaps right before and after it. The entry is not
+// extended further than the days it covers. Open entries are only extended backwards.
+fn fill_entry(work: &mut Work, id: usize, exclusions: &Exclusions) -> Result<TimeEntry> {
+    let entry = match work.get(id) {
+        Some(e) => e.clone(),
+        None => bail!("ID '@{}' does not correspond to any tracking.", id),
+    };
+    let range = entry.range();
+
+    let mut from = range.from();
+    let day = local_midnight(from.with_timezone(&Local).date_naive())?;
+    if let Ok(before) = Range::new(day.into(), Some(from)) {
+        let gaps = work.gaps(&before, &exclusions.ranges(&before));
+        if let Some(gap) = gaps.last().filter(|g| g.to() == Some(from)) {
+            from = gap.from();
+        }
+    }
+
+    let mut to = range.to();
+    if let Some(end) = to {
+        let last_day = (end - Duration::seconds(1))
+            .with_timezone(&Local)
+            .date_naive();
+        let night = local_midnight(last_day + Duration::days(1))?;
+        if let Ok(after) = Range::new(end, Some(night.into())) {
+            let gaps = work.gaps(&after, &exclusions.ranges(&after));
+            if let Some(gap) = gaps.first().filter(|g| g.from() == end) {
+                to = gap.to();
+            }
+        }
+    }
+
+    // Filling a gap never moves the entry past another one, so the ID stays valid.
+    work.replace(
+        id,
+        TimeEntry::new(
+            Range::new(from, to)?,
+            entry.tags().to_vec(),
+            entry.annotation().map(String::from),
+        ),
+    )?;
+
+    Ok(work.get(id).unwrap().clone())
+}
+
+// Replace the entry with the given id by its two halves, split at `when` or in the middle if
+// `when` is not given. The halves keep the tags and annotation of the entry.
+fn split_entry(
+    work: &mut Work,
+    id: usize,
+    when: Option<DateTime<Utc>>,
+) -> Result<(TimeEntry, TimeEntry)> {
+    let entry = match work.get(id) {
+        Some(e) => e.clone(),
+        None => bail!("ID '@{}' does not correspond to any tracking.", id),
+    };
+
+    let (first, second) = match when {
+        Some(w) => entry.range().split_at(w)?,
+        None => entry.range().split()?,
+    };
+
+    let half = |range| {
+        TimeEntry::new(
+            range,
+            entry.tags().to_vec(),
+            entry.annotation().map(String::from),
+        )
+    };
+    work.replace(id, half(first))?;
+    work.insert(half(second));
+
+    Ok((inserted(work, &first), inserted(work, &second)))
+}
+
+// Return the entry inserted for the given Range, with its new ID.
+fn inserted(work: &Work, range: &Range) -> TimeEntry {
+    work.entries()
+        .iter()
+        .find(|e| e.location().is_none() && e.range() == range)
+        .unwrap()
+        .clone()
+}
+
+// Remove the entries with the given ids and return them.
+fn delete_entries(work: &mut Work, ids: &[usize]) -> Result<Vec<TimeEntry>> {
+    let mut entries = vec![];
+    for id in ids {
+        match work.get(*id) {
+            Some(e) => entries.push(e.clone()),
+            None => bail!("ID '@{}' does not correspond to any tracking.", id),
+        }
+    }
+
+    // Removing an entry renumbers the older ones, so the oldest are removed first.
+    let mut ids = ids.to_vec();
+    ids.sort_unstable_by(|a, b| b.cmp(a));
+    ids.dedup();
+    for id in ids {
+        work.remove(id)?;
+    }
+
+    Ok(entries)
+}
+
+// The start and end of an entry, that identify it whatever its ID, tags and annotation.
+type Span = (DateTime<Utc>, Option<DateTime<Utc>>);
+
+// Return the pairs of overlapping entries and the open entries. A RangeSet of the entries would
+// merge them, so the pairs are found by comparing the entries.
+fn problems(work: &Work) -> (BTreeSet<(Span, Span)>, BTreeSet<Span>) {
+    let mut ranges: Vec<&Range> = work.entries().iter().map(|e| e.range()).collect();
+    ranges.sort_by_key(|r| r.from());
+
+    let span = |r: &Range| (r.from(), r.to());
+    let mut overlaps = BTreeSet::new();
+    for (i, range) in ranges.iter().enumerate() {
+        for other in ranges[i + 1..]
+            .iter()
+            .take_while(|r| range.to().is_none_or(|to| r.from() < to))
+        {
+            overlaps.insert((span(range), span(other)));
+        }
+    }
+    let open = ranges.iter().filter(|r| r.is_open()).map(|r| span(r));
+
+    (overlaps, open.collect())
+}
+
+/// The changes made by editor operations, as returned by `dry_run`.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Changes {
+    added: Vec<TimeEntry>,
+    removed: Vec<TimeEntry>,
+    modified: Vec<(TimeEntry, TimeEntry)>,
+}
+
+impl Changes {
+    /// The new entries, with the IDs they would have.
+    pub fn added(&self) -> &[TimeEntry] {
+        &self.added
+    }
+
+    /// The deleted entries, with their current IDs.
+    pub fn removed(&self) -> &[TimeEntry] {
+        &self.removed
+    }
+
+    /// The modified entries, before and after the change. A split entry is modified into its first
+    /// half, and its second half is added.
+    pub fn modified(&self) -> &[(TimeEntry, TimeEntry)] {
+        &self.modified
+    }
+
+    /// Return true if nothing would change.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// A set of editions staged on an in-memory copy of the database, then written all at once.
+///
+/// Each operation applies to the result of the previous ones, with the IDs renumbered after each
+/// of them. An operation that fails leaves the staged entries unchanged. `commit` writes the data
+/// files, `tags.data` and `undo.data` together, so that the database is never left half-written,
+/// and the whole transaction is undone as one by `timew undo`. Dropping the transaction, or
+/// calling `rollback`, discards the staged editions.
+pub struct Transaction {
+    data_path: PathBuf,
+    original: Work,
+    work: Work,
+    exclusions: Exclusions,
+}
+
+impl Transaction {
+    /// Start a transaction on the database and with the exclusions of the user.
+    pub fn begin() -> Result<Transaction> {
+        Transaction::new(&config::data_path()?, exclusions()?)
+    }
+
+    /// Start a transaction on the database in data_path, with the given exclusions for `fill`.
+    pub fn new(data_path: &Path, exclusions: Exclusions) -> Result<Transaction> {
+        let work = Work::load_all(data_path)?;
+        Ok(Transaction {
+            data_path: data_path.to_path_buf(),
+            original: work.clone(),
+            work,
+            exclusions,
+        })
+    }
+
+    /// Return the entries as they will be stored by `commit`.
+    pub fn work(&self) -> &Work {
+        &self.work
+    }
+
+    // Apply the edition on a copy of the staged entries, that replaces them only on success.
+    fn apply<T>(&mut self, f: impl FnOnce(&mut Work, &Exclusions) -> Result<T>) -> Result<T> {
+        let mut work = self.work.clone();
+        let result = f(&mut work, &self.exclusions)?;
+        self.work = work;
+        Ok(result)
+    }
+
+    /// Return the IDs of the staged entries that match the Filter, to apply an operation to all of
+    /// them. Fail if no entry matches, as operations given no ID apply to the open entry.
+    pub fn select(&self, filter: &Filter) -> Result<Vec<usize>> {
+        let ids = filter.ids(&self.work);
+        if ids.is_empty() {
+            bail!("No interval matches the filter.");
+        }
+
+        Ok(ids)
+    }
+
+    /// Return the changes staged since the transaction started.
+    pub fn changes(&self) -> Result<Changes> {
+        let mut changes = Changes::default();
+        for change in self.work.changes()? {
+            match change {
+                (Some(before), Some(after)) => changes.modified.push((before, after)),
+                (Some(before), None) => changes.removed.push(before),
+                (None, Some(after)) => changes.added.push(after),
+                (None, None) => {}
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Stage `editor::start` at the given date/time.
+    pub fn start(&mut self, now: DateTime<Utc>, tags: &[&str], fill: bool) -> Result<TimeEntry> {
+        self.apply(|work, exclusions| start_entry(work, now, tags, fill.then_some(exclusions)))
+    }
+
+    /// Stage `editor::track`.
+    pub fn track(&mut self, range: Range, tags: &[&str], fill: bool) -> Result<TimeEntry> {
+        self.apply(|work, exclusions| track_entry(work, range, tags, fill.then_some(exclusions)))
+    }
+
+    /// Stage `editor::delete`.
+    pub fn delete(&mut self, ids: &[usize]) -> Result<Vec<TimeEntry>> {
+        self.apply(|work, _| delete_entries(work, ids))
+    }
+
+    /// Stage `editor::fill`.
+    pub fn fill(&mut self, id: usize) -> Result<TimeEntry> {
+        self.apply(|work, exclusions| fill_entry(work, id, exclusions))
+    }
+
+    /// Stage `editor::modify_start`.
+    pub fn modify_start(&mut self, id: usize, when: DateTime<Utc>) -> Result<TimeEntry> {
+        self.apply(|work, _| modify_entry(work, id, Some(when), None))
+    }
+
+    /// Stage `editor::modify_end`.
+    pub fn modify_end(&mut self, id: usize, when: DateTime<Utc>) -> Result<TimeEntry> {
+        self.apply(|work, _| modify_entry(work, id, None, Some(when)))
+    }
+
+    /// Stage `editor::annotate`.
+    pub fn annotate(&mut self, ids: &[usize], text: &str) -> Result<Vec<TimeEntry>> {
+        self.apply(|work, _| annotate_entries(work, ids, text))
+    }
+
+    /// Stage `editor::split`.
+    pub fn split(&mut self, id: usize) -> Result<(TimeEntry, TimeEntry)> {
+        self.apply(|work, _| split_entry(work, id, None))
+    }
+
+    /// Stage `editor::split_at`.
+    pub fn split_at(&mut self, id: usize, when: DateTime<Utc>) -> Result<(TimeEntry, TimeEntry)> {
+        self.apply(|work, _| split_entry(work, id, Some(when)))
+    }
+
+    /// Stage `editor::tag`.
+    pub fn tag(&mut self, ids: &[usize], tags: &[&str]) -> Result<Vec<TimeEntry>> {
+        self.apply(|work, _| tag_entries(work, ids, tags))
+    }
+
+    /// Stage `editor::untag`.
+    pub fn untag(&mut self, ids: &[usize], tags: &[&str]) -> Result<Vec<TimeEntry>> {
+        self.apply(|work, _| untag_entries(work, ids, tags))
+    }
+
+    /// Stage `editor::retag`.
+    pub fn retag(&mut self, ids: &[usize], tags: &[&str]) -> Result<Vec<TimeEntry>> {
+        self.apply(|work, _| retag_entries(work, ids, |_| to_tags(tags)))
+    }
+
+    // Fail if the editions add overlapping intervals or a second open interval.
+    fn check(&self) -> Result<()> {
+        // Overlaps and open intervals already in the database are not the fault of the editions.
+        let (overlaps, open) = problems(&self.work);
+        let (original_overlaps, original_open) = problems(&self.original);
+        if !overlaps.is_subset(&original_overlaps) {
+            bail!("You cannot overlap intervals. Correct the start/end time.");
+        }
+        if open.len() > 1 && !open.is_subset(&original_open) {
+            bail!("There cannot be more than one open interval.");
+        }
+
+        Ok(())
+    }
+
+    /// Check the staged entries and write them to the database. The editions cannot add
+    /// overlapping intervals nor a second open interval. If the database was modified since the
+    /// transaction started, nothing is written and a `ConflictError` is returned.
+    pub fn commit(self) -> Result<()> {
+        if !self.work.is_modified() {
+            return Ok(());
+        }
+
+        self.check()?;
+        self.work.store(&self.data_path)
+    }
+
+    /// Discard the staged editions.
+    pub fn rollback(self) {}
+}
+
+// Apply the given edition in a transaction on the whole database. If another program modified
+// the database in the meantime, the edition is not applied again, as the IDs it was given may
+// now designate other entries, and a `ConflictError` is returned.
+fn edit<T>(f: impl FnOnce(&mut Transaction) -> Result<T>) -> Result<T> {
+    let mut transaction = Transaction::begin()?;
+    let result = f(&mut transaction)?;
+    transaction.commit()?;
+
+    Ok(result)
+}
+
+// Return the given ids, or the id of the open entry if none is given, like timew does.
+fn ids_or_open(work: &Work, ids: &[usize]) -> Result<Vec<usize>> {
+    if !ids.is_empty() {
+        return Ok(ids.to_vec());
+    }
+
+    match work.get(1) {
+        Some(e) if e.range().is_open() => Ok(vec![1]),
+        _ => bail!("At least one ID must be specified."),
+    }
+}
+
+// Move the start and end of the entry with the given id. The entry cannot overlap its neighbours.
+fn modify_entry(
+    work: &mut Work,
+    id: usize,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<TimeEntry> {
+    let entry = match work.get(id) {
+        Some(e) => e.clone(),
+        None => bail!("ID '@{}' does not correspond to any tracking.", id),
+    };
+
+    let to = match to {
+        Some(_) if entry.range().is_open() => {
+            bail!("Cannot modify end of open interval @{}: stop it first.", id)
+        }
+        Some(t) => Some(t),
+        None => entry.range().to(),
+    };
+    let range = Range::new(from.unwrap_or_else(|| entry.range().from()), to)?;
+    if range.is_open() && range.from() > Utc::now() {
+        bail!("An open interval cannot start in the future.");
+    }
+    ensure_no_overlap(work, &range, Some(id))?;
+
+    // Without overlap, the entry keeps its place between its neighbours, and its ID.
+    work.replace(
+        id,
+        TimeEntry::new(
+            range,
+            entry.tags().to_vec(),
+            entry.annotation().map(String::from),
+        ),
+    )?;
+
+    Ok(work.get(id).unwrap().clone())
+}
+
+// Replace the entries with the given ids by the result of `f` and return the new entries. `f` must
+// keep the Range of the entries, so that the IDs stay valid.
+fn update_entries(
+    work: &mut Work,
+    ids: &[usize],
+    f: impl Fn(&TimeEntry) -> TimeEntry,
+) -> Result<Vec<TimeEntry>> {
+    let ids = ids_or_open(work, ids)?;
+    let mut entries = vec![];
+    for id in &ids {
+        match work.get(*id) {
+            Some(e) => entries.push(f(e)),
+            None => bail!("ID '@{}' does not correspond to any tracking.", id),
+        }
+    }
+
+    for (id, entry) in ids.iter().zip(entries) {
+        work.replace(*id, entry)?;
+    }
+
+    Ok(ids
+        .iter()
+        .map(|id| work.get(*id).unwrap().clone())
+        .collect())
+}
+
+// Replace the tags of the entries with the given ids by the result of `f` and return the new
+// entries.
+fn retag_entries(
+    work: &mut Work,
+    ids: &[usize],
+    f: impl Fn(&[String]) -> Vec<String>,
+) -> Result<Vec<TimeEntry>> {
+    update_entries(work, ids, |entry| {
+        TimeEntry::new(
+            *entry.range(),
+            f(entry.tags()),
+            entry.annotation().map(String::from),
+        )
+    })
+}
+
+fn annotate_entries(work: &mut Work, ids: &[usize], text: &str) -> Result<Vec<TimeEntry>> {
+    let annotation = if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    };
+
+    update_entries(work, ids, |entry| {
+        TimeEntry::new(*entry.range(), entry.tags().to_vec(), annotation.clone())
+    })
+}
+
+fn tag_entries(work: &mut Work, ids: &[usize], tags: &[&str]) -> Result<Vec<TimeEntry>> {
+    retag_entries(work, ids, |current| {
+        let mut new = current.to_vec();
+        for tag in tags {
+            if !new.iter().any(|t| t == tag) {
+                new.push(tag.to_string());
+            }
+        }
+        new
+    })
+}
+
+fn untag_entries(work: &mut Work, ids: &[usize], tags: &[&str]) -> Result<Vec<TimeEntry>> {
+    retag_entries(work, ids, |current| {
+        current
+            .iter()
+            .filter(|t| !tags.contains(&t.as_str()))
+            .cloned()
+            .collect()
+    })
+}
+
+/// Apply the given editions on the database of the user without writing anything, and return the
+/// changes they would make. Any editor operation can be previewed this way, for example
+/// `dry_run(|t| t.retag(&[1, 2], &["x"]))` for `retag(&[1, 2], &["x"])`. The editions are checked
+/// like on `Transaction::commit`, and the error that the commit would return is returned.
+pub fn dry_run<T>(f: impl FnOnce(&mut Transaction) -> Result<T>) -> Result<Changes> {
+    let mut transaction = Transaction::begin()?;
+    f(&mut transaction)?;
+    transaction.check()?;
+    transaction.changes()
+}
+
+/// Stop the current tracking, start a new tracking with the tags, separated by spaces, and return
+/// the time entry.
 pub fn start(tags: &str) -> Result<TimeEntry> {
-    bail!("Not implemented yet");
+    start_with(&tags.split_whitespace().collect::<Vec<&str>>(), false)
+}
+
+/// Like `start`, with a list of tags. With `fill`, the new entry is extended backwards to the
+/// previous entry or exclusion, like the `:fill` hint of timew.
+pub fn start_with(tags: &[&str], fill: bool) -> Result<TimeEntry> {
+    edit(|t| t.start(Utc::now(), tags, fill))
+}
+
+/// Add a new entry for the given closed Range, like `timew track`. The Range cannot overlap an
+/// existing entry. With `fill`, the new entry is extended to the previous and next entries or
+/// exclusions, like the `:fill` hint of timew.
+pub fn track(range: Range, tags: &[&str], fill: bool) -> Result<TimeEntry> {
+    edit(|t| t.track(range, tags, fill))
+}
+
+/// Extend the entry with the given id backwards and forwards to fill the gaps up to the
+/// neighbouring entries or the exclusions of the configuration. The entry is not extended beyond
+/// the days it covers. Return the modified entry.
+pub fn fill(id: usize) -> Result<TimeEntry> {
+    edit(|t| t.fill(id))
+}
+
+/// Move the start of the entry with the given id, like `timew modify start`. The entry can be
+/// open. The new start cannot make the entry overlap another one, and must be at least one second
+/// before its end. Return the modified entry.
+pub fn modify_start(id: usize, when: DateTime<Utc>) -> Result<TimeEntry> {
+    edit(|t| t.modify_start(id, when))
+}
+
+/// Move the end of the closed entry with the given id, like `timew modify end`. The new end cannot
+/// make the entry overlap another one, and must be at least one second after its start. Return
+/// the modified entry.
+pub fn modify_end(id: usize, when: DateTime<Utc>) -> Result<TimeEntry> {
+    edit(|t| t.modify_end(id, when))
+}
+
+/// Set the annotation of the entries with the given ids, like `timew annotate`. An empty text
+/// removes the annotation. If no id is given, the open entry is annotated.
+/// Return the modified entries.
+pub fn annotate(ids: &[usize], text: &str) -> Result<Vec<TimeEntry>> {
+    edit(|t| t.annotate(ids, text))
+}
+
+/// Delete the entries with the given ids, like `timew delete`. Return the deleted entries.
+pub fn delete(ids: &[usize]) -> Result<Vec<TimeEntry>> {
+    edit(|t| t.delete(ids))
+}
+
+/// Split the entry with the given id in 2 entries of the same duration. Both entries keep the tags
+/// and the annotation of the original entry. Return the new entries, the oldest first.
+pub fn split(id: usize) -> Result<(TimeEntry, TimeEntry)> {
+    edit(|t| t.split(id))
+}
+
+/// Split the entry with the given id at the given date/time. Both entries keep the tags and the
+/// annotation of the original entry. Return the new entries, the oldest first.
+pub fn split_at(id: usize, when: DateTime<Utc>) -> Result<(TimeEntry, TimeEntry)> {
+    edit(|t| t.split_at(id, when))
+}
+
+/// Add the given tags to the entries with the given ids, like `timew tag`. Tags already set on an
+/// entry are not added again. If no id is given, the open entry is tagged.
+/// Return the modified entries.
+pub fn tag(ids: &[usize], tags: &[&str]) -> Result<Vec<TimeEntry>> {
+    edit(|t| t.tag(ids, tags))
+}
+
+/// Remove the given tags from the entries with the given ids, like `timew untag`. If no id is
+/// given, the open entry is untagged.
+/// Return the modified entries.
+pub fn untag(ids: &[usize], tags: &[&str]) -> Result<Vec<TimeEntry>> {
+    edit(|t| t.untag(ids, tags))
+}
+
+/// Replace the tags of the entries with the given ids by the given tags, like `timew retag`. If no
+/// id is given, the open entry is retagged.
+/// Return the modified entries.
+pub fn retag(ids: &[usize], tags: &[&str]) -> Result<Vec<TimeEntry>> {
+    edit(|t| t.retag(ids, tags))
+}
+
+#[cfg(test)]
+mod editor_tests {
+    use crate::config::Config;
+    use crate::data::{ConflictError, Range, TagsData, TestDir, TimeEntry, Work};
+    use crate::editor::{
+        annotate_entries, fill_entry, modify_entry, retag_entries, split_entry, start_entry,
+        tag_entries, track_entry, untag_entries, Transaction,
+    };
+    use crate::filter::Filter;
+    use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+    use std::fs;
+
+    fn parse_date_time(date: &str) -> DateTime<Utc> {
+        let d = NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ").unwrap();
+        Utc.from_utc_datetime(&d)
+    }
+
+    // Return the given time of Monday 11 July 2022, in local time
+    fn local(hour: u32, min: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(2022, 7, 11, hour, min, 0)
+            .unwrap()
+            .into()
+    }
+
+    fn local_range(from: (u32, u32), to: (u32, u32)) -> Range {
+        Range::new(local(from.0, from.1), Some(local(to.0, to.1))).unwrap()
+    }
+
+    // Create a database in a temporary directory, with the given data files
+    fn database(name: &str, files: &[(&str, &str)]) -> TestDir {
+        let path = TestDir::new(&format!("editor-{}", name));
+        for (file, content) in files {
+            fs::write(path.join(file), content).unwrap();
+        }
+
+        path
+    }
+
+    #[test]
+    fn test_split() {
+        let path = database(
+            "split",
+            &[(
+                "2022-07.data",
+                "inc 20220711T080000Z - 20220711T090000Z # a\n\
+                 inc 20220711T100000Z - 20220711T120000Z # b \"c d\" # \"note\"\n",
+            )],
+        );
+
+        let mut work = Work::load_all(&path).unwrap();
+        let (first, second) = split_entry(&mut work, 1, None).unwrap();
+        work.store(&path).unwrap();
+
+        assert_eq!(
+            *first.range(),
+            "20220711T100000Z - 20220711T110000Z"
+                .parse::<Range>()
+                .unwrap()
+        );
+        assert_eq!((first.id(), second.id()), (2, 1));
+        assert_eq!(second.tags(), vec!["b", "c d"]);
+        assert_eq!(second.annotation(), Some("note"));
+        assert_eq!(
+            fs::read_to_string(path.join("2022-07.data")).unwrap(),
+            "inc 20220711T080000Z - 20220711T090000Z # a\n\
+             inc 20220711T100000Z - 20220711T110000Z # b \"c d\" # \"note\"\n\
+             inc 20220711T110000Z - 20220711T120000Z # b \"c d\" # \"note\"\n"
+        );
+
+        // Unknown ID
+        assert!(split_entry(&mut work, 4, None).is_err());
+    }
+
+    #[test]
+    fn test_split_at_across_months() {
+        let path = database(
+            "split-months",
+            &[
+                (
+                    "2022-07.data",
+                    "inc 20220730T120000Z - 20220802T120000Z # a\n",
+                ),
+                (
+                    "2022-08.data",
+                    "inc 20220803T120000Z - 20220803T130000Z # b\n",
+                ),
+            ],
+        );
+
+        let mut work = Work::load_all(&path).unwrap();
+        split_entry(&mut work, 2, Some(parse_date_time("20220801T120000Z"))).unwrap();
+        work.store(&path).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(path.join("2022-07.data")).unwrap(),
+            "inc 20220730T120000Z - 20220801T120000Z # a\n"
+        );
+        assert_eq!(
+            fs::read_to_string(path.join("2022-08.data")).unwrap(),
+            "inc 20220801T120000Z - 20220802T120000Z # a\n\
+             inc 20220803T120000Z - 20220803T130000Z # b\n"
+        );
+
+        // Split outside of the entry
+        assert!(split_entry(&mut work, 1, Some(parse_date_time("20220801T120000Z"))).is_err());
+    }
+
+    #[test]
+    fn test_tag_untag_retag() {
+        let path = database(
+            "tag",
+            &[
+                (
+                    "2022-07.data",
+                    "inc 20220711T080000Z - 20220711T090000Z # a \"b c\"\n\
+                     inc 20220711T100000Z - 20220711T120000Z # b # \"note\"\n",
+                ),
+                (
+                    "tags.data",
+                    "{\n  \"a\":{\"count\":1},\n  \"b\":{\"count\":1},\n  \"b c\":{\"count\":1}\n}",
+                ),
+            ],
+        );
+
+        let mut work = Work::load_all(&path).unwrap();
+        let tagged = tag_entries(&mut work, &[1, 2], &["b", "x-y"]).unwrap();
+        assert_eq!(tagged[0].tags(), vec!["b", "x-y"]);
+        assert_eq!(tagged[1].tags(), vec!["a", "b c", "b", "x-y"]);
+        assert_eq!(tagged[0].annotation(), Some("note"));
+
+        let untagged = untag_entries(&mut work, &[2], &["a", "unknown"]).unwrap();
+        assert_eq!(untagged[0].tags(), vec!["b c", "b", "x-y"]);
+        work.store(&path).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(path.join("2022-07.data")).unwrap(),
+            "inc 20220711T080000Z - 20220711T090000Z # \"b c\" b \"x-y\"\n\
+             inc 20220711T100000Z - 20220711T120000Z # b \"x-y\" # \"note\"\n"
+        );
+        let tags = TagsData::load(&path).unwrap();
+        assert_eq!(
+            tags.tags().collect::<Vec<_>>(),
+            vec![("a", 0), ("b", 2), ("b c", 1), ("x-y", 2)]
+        );
+
+        let mut work = Work::load_all(&path).unwrap();
+        retag_entries(&mut work, &[1], |_| vec![]).unwrap();
+        work.store(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(path.join("2022-07.data")).unwrap(),
+            "inc 20220711T080000Z - 20220711T090000Z # \"b c\" b \"x-y\"\n\
+             inc 20220711T100000Z - 20220711T120000Z # # \"note\"\n"
+        );
+        assert_eq!(TagsData::load(&path).unwrap().count("b"), 1);
+
+        // Unknown ID, and no ID without an open entry
+        assert!(tag_entries(&mut work, &[1, 3], &["c"]).is_err());
+        assert!(tag_entries(&mut work, &[], &["c"]).is_err());
+    }
+
+    #[test]
+    fn test_annotate() {
+        let path = database(
+            "annotate",
+            &[(
+                "2022-07.data",
+                "inc 20220711T080000Z - 20220711T090000Z # a\n\
+                 inc 20220711T100000Z # # \"note\"\n",
+            )],
+        );
+
+        let mut work = Work::load_all(&path).unwrap();
+
+        // Without ID, the open entry is annotated
+        let annotated = annotate_entries(&mut work, &[], "TICKET-1 \"quoted\" C:\\dir").unwrap();
+        assert_eq!(annotated[0].id(), 1);
+        assert_eq!(
+            annotated[0].annotation(),
+            Some("TICKET-1 \"quoted\" C:\\dir")
+        );
+
+        annotate_entries(&mut work, &[2], "summary").unwrap();
+        work.store(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(path.join("2022-07.data")).unwrap(),
+            "inc 20220711T080000Z - 20220711T090000Z # a # \"summary\"\n\
+             inc 20220711T100000Z # # \"TICKET-1 \\\"quoted\\\" C:\\\\dir\"\n"
+        );
+
+        let mut work = Work::load_all(&path).unwrap();
+        assert_eq!(
+            work.get(1).unwrap().annotation(),
+            Some("TICKET-1 \"quoted\" C:\\dir")
+        );
+        let cleared = annotate_entries(&mut work, &[1, 2], "").unwrap();
+        assert!(cleared.iter().all(|e| e.annotation().is_none()));
+        work.store(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(path.join("2022-07.data")).unwrap(),
+            "inc 20220711T080000Z - 20220711T090000Z # a\n\
+             inc 20220711T100000Z\n"
+        );
+    }
+
+    #[test]
+    fn test_track_and_fill() {
+        let path = database("fill", &[]);
+        let exclusions = "exclusions.monday = <8:00 12:00-13:00 >18:00"
+            .parse::<Config>()
+            .unwrap()
+            .exclusions()
+            .unwrap();
+
+        let mut work = Work::load_all(&path).unwrap();
+        track_entry(&mut work, local_range((8, 30), (9, 0)), &["a"], None).unwrap();
+
+        // Filled up to the previous entry and the lunch exclusion
+        let entry = track_entry(
+            &mut work,
+            local_range((10, 0), (11, 0)),
+            &["b"],
+            Some(&exclusions),
+        )
+        .unwrap();
+        assert_eq!(*entry.range(), local_range((9, 0), (12, 0)));
+        assert_eq!(entry.id(), 1);
+
+        // Overlapping entries are refused
+        assert!(track_entry(&mut work, local_range((11, 0), (14, 0)), &["c"], None).is_err());
+
+        // Filled up to the start and end of the day exclusions
+        let entry = track_entry(&mut work, local_range((15, 0), (16, 0)), &["c"], None).unwrap();
+        assert_eq!(entry.id(), 1);
+        let entry = fill_entry(&mut work, 3, &exclusions).unwrap();
+        assert_eq!(*entry.range(), local_range((8, 0), (9, 0)));
+        let entry = fill_entry(&mut work, 1, &exclusions).unwrap();
+        assert_eq!(*entry.range(), local_range((13, 0), (18, 0)));
+    }
+
+    #[test]
+    fn test_start() {
+        let path = database("start", &[]);
+        let exclusions = Config::default().exclusions().unwrap();
+
+        let mut work = Work::load_all(&path).unwrap();
+        track_entry(&mut work, local_range((9, 0), (10, 0)), &["a"], None).unwrap();
+
+        let entry = start_entry(&mut work, local(11, 0), &["b"], None).unwrap();
+        assert_eq!(entry.range().from(), local(11, 0));
+        assert!(entry.range().is_open());
+
+        // The open entry is stopped, and the new one filled back to it
+        let entry = start_entry(&mut work, local(12, 0), &["c"], Some(&exclusions)).unwrap();
+        assert_eq!(entry.range().from(), local(12, 0));
+        assert_eq!(entry.tags(), vec!["c"]);
+        assert_eq!(*work.get(2).unwrap().range(), local_range((11, 0), (12, 0)));
+
+        // Filled back to the end of the previous entry
+        work.remove(1).unwrap();
+        work.remove(1).unwrap();
+        let entry = start_entry(&mut work, local(12, 0), &["d"], Some(&exclusions)).unwrap();
+        assert_eq!(entry.range().from(), local(10, 0));
+    }
+
+    #[test]
+    fn test_modify() {
+        let path = database("modify", &[]);
+
+        let mut work = Work::load_all(&path).unwrap();
+        track_entry(&mut work, local_range((9, 0), (10, 0)), &["a"], None).unwrap();
+        track_entry(&mut work, local_range((11, 0), (12, 0)), &["b"], None).unwrap();
+        start_entry(&mut work, local(13, 0), &["c"], None).unwrap();
+
+        let entry = modify_entry(&mut work, 2, Some(local(10, 0)), None).unwrap();
+        assert_eq!(*entry.range(), local_range((10, 0), (12, 0)));
+        assert_eq!(entry.tags(), vec!["b"]);
+        let entry = modify_entry(&mut work, 2, None, Some(local(12, 30))).unwrap();
+        assert_eq!(*entry.range(), local_range((10, 0), (12, 30)));
+
+        // The start of the open entry can be modified, not its end
+        let entry = modify_entry(&mut work, 1, Some(local(12, 45)), None).unwrap();
+        assert_eq!(entry.range().from(), local(12, 45));
+        assert!(entry.range().is_open());
+        assert!(modify_entry(&mut work, 1, None, Some(local(14, 0))).is_err());
+
+        // Overlapping the neighbours
+        assert!(modify_entry(&mut work, 3, None, Some(local(10, 30))).is_err());
+        assert!(modify_entry(&mut work, 1, Some(local(12, 0)), None).is_err());
+
+        // Invalid ranges
+        assert!(modify_entry(&mut work, 3, Some(local(10, 0)), None).is_err());
+        assert!(modify_entry(&mut work, 3, None, Some(local(8, 0))).is_err());
+        assert!(modify_entry(
+            &mut work,
+            1,
+            Some(Utc::now() + chrono::Duration::hours(1)),
+            None
+        )
+        .is_err());
+        assert!(modify_entry(&mut work, 4, Some(local(8, 0)), None).is_err());
+    }
+
+    #[test]
+    fn test_transaction() {
+        let data = "inc 20220711T080000Z - 20220711T090000Z # a\n\
+                    inc 20220711T100000Z - 20220711T120000Z # b\n";
+        let tags = "{\n  \"a\":{\"count\":1},\n  \"b\":{\"count\":1}\n}";
+        let path = database(
+            "transaction",
+            &[("2022-07.data", data), ("tags.data", tags)],
+        );
+        let exclusions = Config::default().exclusions().unwrap();
+
+        // Rollback
+        let mut transaction = Transaction::new(&path, exclusions.clone()).unwrap();
+        transaction.delete(&[1, 2]).unwrap();
+        assert!(transaction.work().entries().is_empty());
+        transaction.rollback();
+        assert_eq!(fs::read_to_string(path.join("2022-07.data")).unwrap(), data);
+        assert!(!path.join("undo.data").exists());
+
+        // A failed operation leaves the staged entries unchanged
+        let mut transaction = Transaction::new(&path, exclusions.clone()).unwrap();
+        assert!(transaction.split(3).is_err());
+        assert!(transaction.delete(&[1, 3]).is_err());
+        assert_eq!(transaction.work().entries().len(), 2);
+
+        // Several operations stored at once
+        transaction
+            .track(
+                "20220801T080000Z - 20220801T090000Z".parse().unwrap(),
+                &["c"],
+                false,
+            )
+            .unwrap();
+        transaction.retag(&[2], &["d"]).unwrap();
+        transaction.split(3).unwrap();
+        transaction.delete(&[2]).unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(path.join("2022-07.data")).unwrap(),
+            "inc 20220711T080000Z - 20220711T083000Z # a\n\
+             inc 20220711T083000Z - 20220711T090000Z # a\n"
+        );
+        assert_eq!(
+            fs::read_to_string(path.join("2022-08.data")).unwrap(),
+            "inc 20220801T080000Z - 20220801T090000Z # c\n"
+        );
+        assert_eq!(TagsData::load(&path).unwrap().count("b"), 0);
+        assert_eq!(
+            fs::read_to_string(path.join("undo.data")).unwrap(),
+            "txn:\n\
+             \x20 type: interval\n\
+             \x20 before: \n\
+             \x20 after: {\"start\":\"20220801T080000Z\",\"end\":\"20220801T090000Z\",\"tags\":[\"c\"]}\n\
+             \x20 type: interval\n\
+             \x20 before: {\"start\":\"20220711T100000Z\",\"end\":\"20220711T120000Z\",\"tags\":[\"b\"]}\n\
+             \x20 after: {\"start\":\"20220711T100000Z\",\"end\":\"20220711T120000Z\",\"tags\":[\"d\"]}\n\
+             \x20 type: interval\n\
+             \x20 before: {\"start\":\"20220711T080000Z\",\"end\":\"20220711T090000Z\",\"tags\":[\"a\"]}\n\
+             \x20 after: {\"start\":\"20220711T080000Z\",\"end\":\"20220711T083000Z\",\"tags\":[\"a\"]}\n\
+             \x20 type: interval\n\
+             \x20 before: \n\
+             \x20 after: {\"start\":\"20220711T083000Z\",\"end\":\"20220711T090000Z\",\"tags\":[\"a\"]}\n\
+             \x20 type: interval\n\
+             \x20 before: {\"start\":\"20220711T100000Z\",\"end\":\"20220711T120000Z\",\"tags\":[\"d\"]}\n\
+             \x20 after: \n"
+        );
+
+        // An open interval can be started after the staged entries
+        let mut transaction = Transaction::new(&path, exclusions.clone()).unwrap();
+        transaction
+            .start(parse_date_time("20220801T100000Z"), &["e"], false)
+            .unwrap();
+        transaction
+            .modify_start(1, parse_date_time("20220801T083000Z"))
+            .unwrap_err();
+        transaction
+            .start(parse_date_time("20220801T110000Z"), &[], false)
+            .unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(
+            fs::read_to_string(path.join("2022-08.data")).unwrap(),
+            "inc 20220801T080000Z - 20220801T090000Z # c\n\
+             inc 20220801T100000Z - 20220801T110000Z # e\n\
+             inc 20220801T110000Z\n"
+        );
+
+        // Nothing is written if another program modified the database in the meantime
+        let mut transaction = Transaction::new(&path, exclusions).unwrap();
+        transaction.delete(&[2]).unwrap();
+        let data = "inc 20220801T080000Z - 20220801T090000Z # c\n";
+        fs::write(path.join("2022-08.data"), data).unwrap();
+        assert!(transaction.commit().unwrap_err().is::<ConflictError>());
+        assert_eq!(fs::read_to_string(path.join("2022-08.data")).unwrap(), data);
+    }
+
+    #[test]
+    fn test_commit_problems() {
+        // a and b already overlap
+        let data = "inc 20220711T080000Z - 20220711T100000Z # a\n\
+                    inc 20220711T090000Z - 20220711T110000Z # b\n\
+                    inc 20220711T120000Z - 20220711T130000Z # c\n\
+                    inc 20220711T140000Z # d\n";
+        let path = database("commit-problems", &[("2022-07.data", data)]);
+        let exclusions = Config::default().exclusions().unwrap();
+        let entry = |line: &str| line.parse::<TimeEntry>().unwrap();
+
+        // The overlap already there does not prevent editing the entries
+        let mut transaction = Transaction::new(&path, exclusions.clone()).unwrap();
+        transaction.retag(&[3, 4], &["e"]).unwrap();
+        transaction.commit().unwrap();
+
+        // Removing the overlap does not allow adding another one
+        let mut transaction = Transaction::new(&path, exclusions.clone()).unwrap();
+        transaction.work.remove(3).unwrap();
+        transaction
+            .work
+            .insert(entry("inc 20220711T123000Z - 20220711T133000Z # f"));
+        // dry_run checks the editions like commit
+        assert!(transaction.check().is_err());
+        assert!(transaction.commit().is_err());
+
+        // Nor can an open interval be added while another one is open
+        let mut transaction = Transaction::new(&path, exclusions).unwrap();
+        transaction.work.insert(entry("inc 20220711T150000Z # g"));
+        assert!(transaction.commit().is_err());
+
+        assert_eq!(
+            fs::read_to_string(path.join("2022-07.data")).unwrap(),
+            "inc 20220711T080000Z - 20220711T100000Z # e\n\
+             inc 20220711T090000Z - 20220711T110000Z # e\n\
+             inc 20220711T120000Z - 20220711T130000Z # c\n\
+             inc 20220711T140000Z # d\n"
+        );
+    }
+
+    #[test]
+    fn test_changes() {
+        let path = database(
+            "changes",
+            &[(
+                "2022-07.data",
+                "inc 20220711T080000Z - 20220711T090000Z # a\n\
+                 inc 20220711T100000Z - 20220711T120000Z # b\n\
+                 inc 20220711T130000Z - 20220711T140000Z # c\n",
+            )],
+        );
+        let exclusions = Config::default().exclusions().unwrap();
+        let mut transaction = Transaction::new(&path, exclusions).unwrap();
+        let loaded = transaction.work().entries().to_vec();
+
+        transaction.tag(&[3], &["x"]).unwrap();
+        transaction.retag(&[3], &["y"]).unwrap();
+        transaction.split(2).unwrap();
+        transaction.tag(&[1], &["c"]).unwrap();
+        transaction.delete(&[1]).unwrap();
+        let range: Range = "20220711T150000Z - 20220711T160000Z".parse().unwrap();
+        transaction.track(range, &["d"], false).unwrap();
+
+        let changes = transaction.changes().unwrap();
+        assert_eq!(changes.removed(), &loaded[0..1]);
+
+        let modified = changes.modified();
+        assert_eq!(modified.len(), 2);
+        assert_eq!(modified[0].0, loaded[2]);
+        assert_eq!(
+            (modified[0].1.id(), modified[0].1.tags()),
+            (4, &["y".to_string()][..])
+        );
+        assert_eq!(modified[1].0, loaded[1]);
+        assert_eq!(modified[1].1.id(), 3);
+        assert_eq!(
+            *modified[1].1.range(),
+            "20220711T100000Z - 20220711T110000Z".parse().unwrap()
+        );
+
+        let added = changes.added();
+        assert_eq!(added.len(), 2);
+        assert_eq!(
+            (added[0].id(), *added[0].range()),
+            (2, "20220711T110000Z - 20220711T120000Z".parse().unwrap())
+        );
+        assert_eq!((added[1].id(), *added[1].range()), (1, range));
+
+        let filter = Filter::new(None).all_of(["b"]);
+        assert_eq!(transaction.select(&filter).unwrap(), vec![2, 3]);
+        assert!(transaction.select(&filter.none_of(["b"])).is_err());
+
+        // Nothing is written
+        transaction.rollback();
+        assert!(!path.join("undo.data").exists());
+    }
 }
@@ -0,0 +1,216 @@
+// This file contains the filters selecting time entries by range and tags, like the filter
+// arguments of timew commands.
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::data::{Range, TimeEntry, Work};
+
+/// A pattern that a tag can match.
+#[derive(Clone, Debug)]
+pub enum TagPattern {
+    /// The tag is exactly the given text.
+    Exact(String),
+    /// The whole tag matches the regular expression.
+    Glob(Regex),
+    /// The regular expression matches a part of the tag.
+    Regex(Regex),
+}
+
+impl TagPattern {
+    /// Create a pattern matching the tag equal to the given text.
+    pub fn exact(tag: &str) -> TagPattern {
+        TagPattern::Exact(tag.to_string())
+    }
+
+    /// Create a pattern matching the tags that match the given glob. In a glob, `*` matches any
+    /// text and `?` matches any character. Other characters only match themselves.
+    pub fn glob(glob: &str) -> Result<TagPattern> {
+        let mut re = String::from("^");
+        for c in glob.chars() {
+            match c {
+                '*' => re.push_str(".*"),
+                '?' => re.push('.'),
+                _ => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+
+        Ok(TagPattern::Glob(Regex::new(&re)?))
+    }
+
+    /// Create a pattern matching the tags in which the given regular expression is found. Use `^`
+    /// and `$` to match whole tags.
+    pub fn regex(re: &str) -> Result<TagPattern> {
+        Ok(TagPattern::Regex(Regex::new(re)?))
+    }
+
+    /// Return true if the tag matches the pattern.
+    pub fn matches(&self, tag: &str) -> bool {
+        match self {
+            TagPattern::Exact(t) => t == tag,
+            TagPattern::Glob(re) | TagPattern::Regex(re) => re.is_match(tag),
+        }
+    }
+
+    // Return true if one of the tags matches the pattern.
+    fn matches_any(&self, tags: &[String]) -> bool {
+        tags.iter().any(|t| self.matches(t))
+    }
+}
+
+impl From<&str> for TagPattern {
+    fn from(tag: &str) -> TagPattern {
+        TagPattern::exact(tag)
+    }
+}
+
+/// Select time entries by Range and tags.
+///
+/// An entry matches the filter if it intersects the Range, if every pattern of `all_of` matches one
+/// of its tags, if at least one pattern of `any_of` matches one of its tags, and if no pattern of
+/// `none_of` matches any of its tags. Empty lists of patterns are ignored. The filter of
+/// `timew summary foo bar` is `Filter::new(None).all_of(["foo", "bar"])`.
+#[derive(Clone, Default, Debug)]
+pub struct Filter {
+    range: Option<Range>,
+    all: Vec<TagPattern>,
+    any: Vec<TagPattern>,
+    none: Vec<TagPattern>,
+}
+
+impl Filter {
+    /// Create a filter matching the entries that intersect the given Range, or all entries if no
+    /// Range is given.
+    pub fn new(range: Option<Range>) -> Filter {
+        Filter {
+            range,
+            ..Filter::default()
+        }
+    }
+
+    /// Add patterns that must all match a tag of the entries.
+    pub fn all_of<P: Into<TagPattern>>(mut self, patterns: impl IntoIterator<Item = P>) -> Filter {
+        self.all.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Add patterns of which one must match a tag of the entries.
+    pub fn any_of<P: Into<TagPattern>>(mut self, patterns: impl IntoIterator<Item = P>) -> Filter {
+        self.any.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Add patterns that must not match any tag of the entries.
+    pub fn none_of<P: Into<TagPattern>>(mut self, patterns: impl IntoIterator<Item = P>) -> Filter {
+        self.none.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Return the Range of the filter.
+    pub fn range(&self) -> Option<&Range> {
+        self.range.as_ref()
+    }
+
+    /// Return true if the filter matches all entries.
+    pub fn is_empty(&self) -> bool {
+        self.range.is_none() && self.all.is_empty() && self.any.is_empty() && self.none.is_empty()
+    }
+
+    /// Return true if the entry matches the filter.
+    pub fn matches(&self, entry: &TimeEntry) -> bool {
+        let tags = entry.tags();
+
+        self.range
+            .is_none_or(|r| entry.range().intersection(&r).is_some())
+            && self.all.iter().all(|p| p.matches_any(tags))
+            && (self.any.is_empty() || self.any.iter().any(|p| p.matches_any(tags)))
+            && !self.none.iter().any(|p| p.matches_any(tags))
+    }
+
+    /// Return the IDs of the entries of the Work that match the filter.
+    pub fn ids(&self, work: &Work) -> Vec<usize> {
+        work.entries()
+            .iter()
+            .filter(|e| self.matches(e))
+            .map(|e| e.id())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use crate::data::{Range, TimeEntry};
+    use crate::filter::{Filter, TagPattern};
+
+    fn entry(range: &str, tags: &[&str]) -> TimeEntry {
+        TimeEntry::new(
+            range.parse().unwrap(),
+            tags.iter().map(|t| t.to_string()).collect(),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_tag_pattern() {
+        assert!(TagPattern::exact("a.b").matches("a.b"));
+        assert!(!TagPattern::exact("a.b").matches("a.bc"));
+
+        let glob = TagPattern::glob("proj-*.?").unwrap();
+        assert!(glob.matches("proj-x.1"));
+        assert!(glob.matches("proj-.1"));
+        assert!(!glob.matches("proj-x.12"));
+        assert!(!glob.matches("myproj-x.1"));
+
+        let re = TagPattern::regex("^ticket-[0-9]+").unwrap();
+        assert!(re.matches("ticket-12"));
+        assert!(re.matches("ticket-12-review"));
+        assert!(!re.matches("ticket-"));
+        assert!(TagPattern::regex("(").is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let a = entry("20220711T080000Z - 20220711T090000Z", &["work", "proj-a"]);
+        let b = entry(
+            "20220712T080000Z - 20220712T090000Z",
+            &["work", "proj-b", "meeting"],
+        );
+        let c = entry("20220713T080000Z", &["home"]);
+
+        let matching = |filter: &Filter| -> Vec<bool> {
+            [&a, &b, &c].iter().map(|e| filter.matches(e)).collect()
+        };
+
+        assert!(Filter::new(None).is_empty());
+        assert_eq!(matching(&Filter::new(None)), [true, true, true]);
+
+        let range: Range = "20220712T000000Z - 20220714T000000Z".parse().unwrap();
+        assert_eq!(matching(&Filter::new(Some(range))), [false, true, true]);
+
+        assert_eq!(
+            matching(&Filter::new(None).all_of(["work", "meeting"])),
+            [false, true, false]
+        );
+        assert_eq!(
+            matching(&Filter::new(None).any_of(["meeting", "home"])),
+            [false, true, true]
+        );
+        assert_eq!(
+            matching(&Filter::new(None).none_of(["meeting"])),
+            [true, false, true]
+        );
+        assert_eq!(
+            matching(
+                &Filter::new(Some(range))
+                    .all_of([TagPattern::glob("proj-*").unwrap()])
+                    .none_of([TagPattern::regex("^meet").unwrap()])
+            ),
+            [false, false, false]
+        );
+        assert_eq!(
+            matching(&Filter::new(None).all_of([TagPattern::glob("proj-*").unwrap()])),
+            [true, true, false]
+        );
+    }
+}
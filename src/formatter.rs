@@ -1,19 +1,1835 @@
 // This file will contain function that are used to format the data in a certain way
 // functions like summ, day, week, month, tags, raw
 
-use anyhow::Result;
-use home::home_dir;
+use anyhow::{bail, ensure, Context, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
 
-use crate::data::{Range, Work};
+use crate::config;
+use crate::config::Config;
+use crate::data::{split_duration, Range, TagAttribution, TagsData, TimeEntry, Work};
+use crate::date::local_date_time;
+use crate::duration::{format_duration, DurationFormat};
+use crate::filter::{Filter, TagPattern};
+use crate::rangeset::RangeSet;
 
 /// Get the raw data for the given time Range.
 ///
 /// If range is not specified, the whole database is retrieved.
 /// The data is always retrieved from the `${HOME}/.timewarrior/data` file.
 pub fn raw(range: Option<Range>) -> Result<Work> {
-    let mut data_path = home_dir().unwrap();
-    data_path.push(".timewarrior");
-    data_path.push("data");
+    Work::load_range(&config::data_path()?, range)
+}
+
+/// Get the raw data matching the given Filter, like `timew export` with a range and tags.
+///
+/// The data is always retrieved from the `${HOME}/.timewarrior/data` file.
+pub fn filtered(filter: &Filter) -> Result<Work> {
+    Work::load_filter(&config::data_path()?, filter)
+}
+
+// Return the local days of the Range the Work was loaded for, or from the first to the last day of
+// the entries when it was loaded without Range.
+fn work_days<Tz: TimeZone>(work: &Work, tz: &Tz) -> Result<Vec<NaiveDate>> {
+    let local = |t: DateTime<Utc>| t.with_timezone(tz).date_naive();
+    let (first, last) = match work.range() {
+        Some(r) => {
+            let to = r
+                .to()
+                .unwrap_or_else(Utc::now)
+                .max(r.from() + Duration::seconds(1));
+            (
+                Some(local(r.from())),
+                Some(local(to - Duration::seconds(1))),
+            )
+        }
+        None => {
+            let mut days = vec![];
+            for entry in work.entries() {
+                days.extend(work.entry_days_in(entry, tz)?.into_iter().map(|(d, _)| d));
+            }
+            (days.iter().min().copied(), days.iter().max().copied())
+        }
+    };
+
+    Ok(match (first, last) {
+        (Some(first), Some(last)) => first.iter_days().take_while(|d| *d <= last).collect(),
+        _ => vec![],
+    })
+}
+
+// A table rendered as text: each cell is padded to the width of the widest cell of its column,
+// left aligned unless its column is right aligned, and the cells are separated by `separator`.
+struct TextTable {
+    separator: &'static str,
+    right: Vec<bool>,
+    widths: Vec<usize>,
+}
+
+impl TextTable {
+    // Create the table of the given rows, with a column for each alignment. Rows can have fewer
+    // cells than columns.
+    fn new(separator: &'static str, right: Vec<bool>, rows: &[Vec<String>]) -> TextTable {
+        let mut widths = vec![0; right.len()];
+        for row in rows {
+            for (w, cell) in widths.iter_mut().zip(row) {
+                *w = (*w).max(cell.chars().count());
+            }
+        }
+
+        TextTable {
+            separator,
+            right,
+            widths,
+        }
+    }
+
+    // Render the cells as a line, without trailing spaces nor line break.
+    fn line(&self, cells: &[String]) -> String {
+        let line: Vec<String> = self
+            .widths
+            .iter()
+            .zip(&self.right)
+            .enumerate()
+            .map(|(i, (w, right))| {
+                let cell = cells.get(i).map_or("", String::as_str);
+                if *right {
+                    format!("{:>w$}", cell, w = w)
+                } else {
+                    format!("{:<w$}", cell, w = w)
+                }
+            })
+            .collect();
+
+        line.join(self.separator).trim_end().to_string()
+    }
+
+    // Render a rule under each column.
+    fn rule(&self) -> String {
+        let rule: Vec<String> = self.widths.iter().map(|w| "-".repeat(*w)).collect();
+        self.line(&rule)
+    }
+}
+
+/// A group of tags, shown as a single row of a Timesheet.
+#[derive(Clone, Debug)]
+pub struct TagGroup {
+    name: String,
+    patterns: Vec<TagPattern>,
+}
+
+impl TagGroup {
+    /// Create a group named `name`, made of the tags that match one of the patterns.
+    pub fn new<P: Into<TagPattern>>(name: &str, patterns: impl IntoIterator<Item = P>) -> TagGroup {
+        TagGroup {
+            name: name.to_string(),
+            patterns: patterns.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Return the name of the group.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// A row of a Timesheet: the time of a tag, or of a group of tags, on each day.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TimesheetRow {
+    label: String,
+    days: Vec<Duration>,
+}
+
+impl TimesheetRow {
+    /// Return the tag or the name of the group of the row. It is empty for the entries without
+    /// tags.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Return the time of the row on each day of the Timesheet.
+    pub fn days(&self) -> &[Duration] {
+        &self.days
+    }
+
+    /// Return the time of the row over all the days.
+    pub fn total(&self) -> Duration {
+        self.days.iter().fold(Duration::zero(), |a, d| a + *d)
+    }
+}
+
+/// A timesheet: the time tracked for each tag, or group of tags, on each day of a Range.
+///
+/// The totals of the days are the time tracked on each day. When the time of the entries with
+/// several tags is fully counted for each tag, the rows add up to more than these totals.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Timesheet {
+    days: Vec<NaiveDate>,
+    rows: Vec<TimesheetRow>,
+    totals: Vec<Duration>,
+}
+
+impl Timesheet {
+    /// Create the timesheet of the entries of the Work, with a column for each day, in the given
+    /// timezone, of the Range the Work was loaded for. Without Range, the columns go from the
+    /// first to the last day of the entries.
+    ///
+    /// Each tag is a row, except the tags that match one of the groups, that are shown in the row
+    /// of the first group they match. The time of the entries with several rows is counted
+    /// according to the attribution.
+    pub fn new_in<Tz: TimeZone>(
+        work: &Work,
+        groups: &[TagGroup],
+        attribution: TagAttribution,
+        tz: &Tz,
+    ) -> Result<Timesheet> {
+        let days = work_days(work, tz)?;
+        let mut entry_days = vec![];
+        for entry in work.entries() {
+            entry_days.push((entry, work.entry_days_in(entry, tz)?));
+        }
+
+        let mut rows: BTreeMap<String, Vec<Duration>> = BTreeMap::new();
+        let mut totals = vec![Duration::zero(); days.len()];
+        for (entry, times) in entry_days {
+            let labels = Timesheet::labels(entry, groups);
+            let labels = match attribution {
+                TagAttribution::First => &labels[..1],
+                _ => &labels[..],
+            };
+
+            for (day, time) in times {
+                let i = match days.binary_search(&day) {
+                    Ok(i) => i,
+                    Err(_) => continue,
+                };
+                totals[i] += time;
+                let times = match attribution {
+                    TagAttribution::Split => split_duration(time, labels.len()),
+                    _ => vec![time; labels.len()],
+                };
+                for (label, time) in labels.iter().zip(times) {
+                    rows.entry(label.clone())
+                        .or_insert_with(|| vec![Duration::zero(); days.len()])[i] += time;
+                }
+            }
+        }
+
+        Ok(Timesheet {
+            days,
+            rows: rows
+                .into_iter()
+                .map(|(label, days)| TimesheetRow { label, days })
+                .collect(),
+            totals,
+        })
+    }
+
+    /// Create the timesheet of the entries of the Work, with a column for each local day
+    pub fn new(work: &Work, groups: &[TagGroup], attribution: TagAttribution) -> Result<Timesheet> {
+        Timesheet::new_in(work, groups, attribution, &Local)
+    }
+
+    // Return the rows of the entry: its tags, or the groups they belong to, without duplicates.
+    // An entry without tags has a single row with an empty label.
+    fn labels(entry: &TimeEntry, groups: &[TagGroup]) -> Vec<String> {
+        let mut labels: Vec<String> = vec![];
+        for tag in entry.tags() {
+            let label = groups
+                .iter()
+                .find(|g| g.patterns.iter().any(|p| p.matches(tag)))
+                .map_or(tag, |g| &g.name);
+            if !labels.contains(label) {
+                labels.push(label.clone());
+            }
+        }
+        if labels.is_empty() {
+            labels.push(String::new());
+        }
+
+        labels
+    }
+
+    /// Return the days of the columns, from the oldest to the most recent one.
+    pub fn days(&self) -> &[NaiveDate] {
+        &self.days
+    }
+
+    /// Return the rows, sorted by label.
+    pub fn rows(&self) -> &[TimesheetRow] {
+        &self.rows
+    }
+
+    /// Return the time tracked on each day.
+    pub fn totals(&self) -> &[Duration] {
+        &self.totals
+    }
+
+    /// Return the time tracked over all the days.
+    pub fn total(&self) -> Duration {
+        self.totals.iter().fold(Duration::zero(), |a, d| a + *d)
+    }
+
+    // Return the cells of the header, the rows and the totals, the empty times being left blank
+    // unless a format is given for them.
+    fn table(&self, day_format: &str, format: DurationFormat, blank: bool) -> Vec<Vec<String>> {
+        let cell = |d: &Duration| {
+            if blank && d.is_zero() {
+                String::new()
+            } else {
+                format_duration(d, format)
+            }
+        };
+        let line = |label: &str, days: &[Duration], total: Duration| {
+            let mut cells = vec![label.to_string()];
+            cells.extend(days.iter().map(cell));
+            cells.push(cell(&total));
+            cells
+        };
+
+        let mut header = vec!["Tag".to_string()];
+        header.extend(self.days.iter().map(|d| d.format(day_format).to_string()));
+        header.push("Total".to_string());
+
+        let mut table = vec![header];
+        for row in &self.rows {
+            table.push(line(&row.label, &row.days, row.total()));
+        }
+        table.push(line("Total", &self.totals, self.total()));
+
+        table
+    }
+
+    /// Render the timesheet as a text table, with the times formatted like `1:30:00`.
+    pub fn text(&self) -> String {
+        let table = self.table("%a %m-%d", DurationFormat::Clock, true);
+        let mut right = vec![true; table[0].len()];
+        right[0] = false;
+        let text_table = TextTable::new("  ", right, &table);
+
+        let mut lines = vec![text_table.line(&table[0]), text_table.rule()];
+        for cells in &table[1..table.len() - 1] {
+            lines.push(text_table.line(cells));
+        }
+        lines.push(text_table.rule());
+        lines.push(text_table.line(&table[table.len() - 1]));
+
+        lines.iter().map(|l| l.clone() + "\n").collect()
+    }
+
+    /// Render the timesheet as CSV, with ISO 8601 days and the times in decimal hours.
+    pub fn csv(&self) -> String {
+        let quote = |c: &String| {
+            if c.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", c.replace('"', "\"\""))
+            } else {
+                c.clone()
+            }
+        };
+
+        self.table("%Y-%m-%d", DurationFormat::DecimalHours, false)
+            .iter()
+            .map(|r| r.iter().map(quote).collect::<Vec<String>>().join(",") + "\n")
+            .collect()
+    }
+
+    /// Render the timesheet as a Markdown table, with the times formatted like `1:30:00`.
+    pub fn markdown(&self) -> String {
+        let table = self.table("%a %m-%d", DurationFormat::Clock, true);
+        let render = |cells: &[String]| {
+            let cells: Vec<String> = cells.iter().map(|c| c.replace('|', "\\|")).collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+
+        let mut rule = vec![":--".to_string()];
+        rule.resize(table[0].len(), "--:".to_string());
+
+        let mut markdown = render(&table[0]);
+        markdown.push_str(&format!("|{}|\n", rule.join("|")));
+        for cells in &table[1..] {
+            markdown.push_str(&render(cells));
+        }
+
+        markdown
+    }
+}
+
+/// Get the timesheet of the given Range, with a column for each local day. See `Timesheet::new_in`.
+///
+/// The data is always retrieved from the `${HOME}/.timewarrior/data` file.
+pub fn timesheet(
+    range: Range,
+    groups: &[TagGroup],
+    attribution: TagAttribution,
+) -> Result<Timesheet> {
+    Timesheet::new(&raw(Some(range))?, groups, attribution)
+}
+
+/// The chart reports of timew, that draw the tracked time of each day along an hour axis.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ChartReport {
+    /// `timew day`, for today by default
+    Day,
+    /// `timew week`, for the current week by default
+    Week,
+    /// `timew month`, for the current month by default
+    Month,
+}
+
+impl ChartReport {
+    // Return the name of the report in the `reports.<name>.*` keys.
+    fn name(&self) -> &'static str {
+        match self {
+            ChartReport::Day => "day",
+            ChartReport::Week => "week",
+            ChartReport::Month => "month",
+        }
+    }
+}
+
+// The colours of the tags without `tags.<tag>.color`, when no `theme.palette.*` is set.
+const PALETTE: [&str; 8] = [
+    "black on red",
+    "black on green",
+    "black on yellow",
+    "black on blue",
+    "black on magenta",
+    "black on cyan",
+    "black on bright red",
+    "black on bright green",
+];
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+const RESET: &str = "\x1b[0m";
+
+// Parse a boolean value of the configuration, like `yes` or `off`.
+fn parse_bool(key: &str, value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "yes" | "y" | "true" | "1" => Ok(true),
+        "off" | "no" | "n" | "false" | "0" => Ok(false),
+        _ => bail!("Invalid value '{}' for '{}'", value, key),
+    }
+}
+
+// Return the number of the 256 colours of a terminal given by name, like `red`, `color12`,
+// `gray4` or `rgb012`.
+fn color_number(name: &str, bright: bool) -> Option<u32> {
+    if let Some(i) = COLOR_NAMES.iter().position(|n| *n == name) {
+        return Some(i as u32 + if bright { 8 } else { 0 });
+    }
+    if let Some(n) = name.strip_prefix("color") {
+        return n.parse().ok().filter(|n| *n < 256);
+    }
+    if let Some(n) = name
+        .strip_prefix("gray")
+        .or_else(|| name.strip_prefix("grey"))
+    {
+        return n.parse::<u32>().ok().filter(|n| *n < 24).map(|n| 232 + n);
+    }
+    let rgb: Vec<u32> = name
+        .strip_prefix("rgb")?
+        .chars()
+        .filter_map(|c| c.to_digit(6))
+        .collect();
+    match rgb[..] {
+        [r, g, b] if name.len() == 6 => Some(16 + 36 * r + 6 * g + b),
+        _ => None,
+    }
+}
+
+// Return the ANSI escape sequence of a colour of the configuration, such as `white on blue`,
+// `bold red`, `color12` or `on rgb012`. An empty colour gives an empty sequence.
+pub(crate) fn ansi_color(color: &str) -> Result<String> {
+    let mut codes = vec![];
+    let (mut background, mut bright) = (false, false);
+    for word in color.to_lowercase().split_whitespace() {
+        match word {
+            "on" => background = true,
+            "bright" => bright = true,
+            "bold" => codes.push("1".to_string()),
+            "underline" => codes.push("4".to_string()),
+            "inverse" => codes.push("7".to_string()),
+            _ => match color_number(word, bright) {
+                Some(n) => {
+                    codes.push(format!("{};5;{}", if background { 48 } else { 38 }, n));
+                    bright = false;
+                }
+                None => bail!("Invalid colour '{}'", color),
+            },
+        }
+    }
+
+    if codes.is_empty() {
+        return Ok(String::new());
+    }
+
+    Ok(format!("\x1b[{}m", codes.join(";")))
+}
+
+/// The settings of a chart report, from the `reports.<name>.*` keys of the configuration, the
+/// `color` key and the colours of the tags and of the theme.
+#[derive(Clone, Debug)]
+pub struct ChartConfig {
+    report: ChartReport,
+    config: Config,
+    lines: usize,
+    month: bool,
+    week: bool,
+    weekday: bool,
+    day: bool,
+    all_hours: bool,
+    cell: u32,
+    spacing: usize,
+    internal_axis: bool,
+    summary: bool,
+    totals: bool,
+    holidays: BTreeMap<NaiveDate, String>,
+    color: bool,
+    palette: Vec<String>,
+    exclusion: String,
+}
+
+impl ChartConfig {
+    /// Read the settings of the report from the configuration. The keys that are not set have the
+    /// defaults of timew.
+    pub fn new(config: &Config, report: ChartReport) -> Result<ChartConfig> {
+        let key = |name: &str| format!("reports.{}.{}", report.name(), name);
+        let get = |name: &str, default: &'static str| {
+            config.get(&key(name)).unwrap_or(default).to_string()
+        };
+        let flag = |name: &str, default: &'static str| parse_bool(&key(name), &get(name, default));
+        let number = |name: &str, default: &'static str| -> Result<u32> {
+            get(name, default)
+                .parse()
+                .with_context(|| format!("Invalid value for '{}'", key(name)))
+        };
+
+        let lines = number("lines", "1")?;
+        ensure!(
+            lines >= 1,
+            "The value for '{}' must be at least 1.",
+            key("lines")
+        );
+        let cell = number("cell", "15")?;
+        ensure!(
+            (1..=60).contains(&cell) && 60 % cell == 0,
+            "The value for '{}' must divide 60.",
+            key("cell")
+        );
+        let all_hours = match get("hours", "all").as_str() {
+            "all" => true,
+            "auto" => false,
+            v => bail!("Invalid value '{}' for '{}'", v, key("hours")),
+        };
+        let internal_axis = match get("axis", "internal").as_str() {
+            "internal" => true,
+            "external" => false,
+            v => bail!("Invalid value '{}' for '{}'", v, key("axis")),
+        };
+
+        let mut palette = vec![];
+        while let Some(c) = config.get(&format!("theme.palette.color{:02}", palette.len() + 1)) {
+            palette.push(ansi_color(c)?);
+        }
+        if palette.is_empty() {
+            palette = PALETTE
+                .iter()
+                .map(|c| ansi_color(c))
+                .collect::<Result<_>>()?;
+        }
+
+        Ok(ChartConfig {
+            report,
+            config: config.clone(),
+            lines: lines as usize,
+            month: flag("month", "yes")?,
+            week: flag("week", "yes")?,
+            weekday: flag("weekday", "yes")?,
+            day: flag("day", "yes")?,
+            all_hours,
+            cell,
+            spacing: number("spacing", "1")? as usize,
+            internal_axis,
+            summary: flag("summary", "yes")?,
+            totals: flag("totals", "no")?,
+            holidays: if flag("holidays", "yes")? {
+                config.holidays()?
+            } else {
+                BTreeMap::new()
+            },
+            color: parse_bool("color", config.get("color").unwrap_or("on"))?,
+            palette,
+            exclusion: ansi_color(
+                config
+                    .get("theme.colors.exclusion")
+                    .unwrap_or("gray8 on gray4"),
+            )?,
+        })
+    }
+
+    /// Return the Range of the report: the `reports.<name>.range` key, such as `:week`, or today,
+    /// the current week or the current month.
+    pub fn range(&self) -> Result<Range> {
+        match self
+            .config
+            .get(&format!("reports.{}.range", self.report.name()))
+        {
+            Some(r) => Range::from_period(r.trim_start_matches(':'), self.config.weekstart()?),
+            None => match self.report {
+                ChartReport::Day => Range::today(),
+                ChartReport::Week => Range::current_week(self.config.weekstart()?),
+                ChartReport::Month => Range::current_month(),
+            },
+        }
+    }
+
+    // Return the label of the day at the start of its row, the month and the week being left
+    // blank when they are the same as on the previous row.
+    fn label(&self, day: &NaiveDate, previous: Option<&NaiveDate>) -> String {
+        let mut parts = vec![];
+        if self.month {
+            parts.push(if previous.is_some_and(|p| p.month() == day.month()) {
+                "   ".to_string()
+            } else {
+                day.format("%b").to_string()
+            });
+        }
+        if self.week {
+            parts.push(
+                if previous.is_some_and(|p| p.iso_week() == day.iso_week()) {
+                    "   ".to_string()
+                } else {
+                    format!("W{:02}", day.iso_week().week())
+                },
+            );
+        }
+        if self.weekday {
+            parts.push(day.format("%a").to_string());
+        }
+        if self.day {
+            parts.push(format!("{:>2}", day.day()));
+        }
+
+        parts.join(" ")
+    }
+
+    // Return the colour of the entry: the colour of its first tag that has one, or else a colour
+    // of the palette given to its first tag.
+    fn entry_color(
+        &self,
+        entry: &TimeEntry,
+        assigned: &mut BTreeMap<String, String>,
+    ) -> Result<String> {
+        for tag in entry.tags() {
+            if let Some(color) = self.config.get(&format!("tags.{}.color", tag)) {
+                return ansi_color(color);
+            }
+        }
+
+        let tag = entry.tags().first().cloned().unwrap_or_default();
+        let next = self.palette[assigned.len() % self.palette.len()].clone();
+        Ok(assigned.entry(tag).or_insert(next).clone())
+    }
+}
+
+// What a cell of a chart shows.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Cell {
+    Empty,
+    Excluded,
+    // The index of the entry tracked during the cell
+    Tracked(usize),
+}
+
+// Render characters with their optional ANSI colour.
+fn render_styled(chars: &[(char, Option<&str>)]) -> String {
+    let mut text = String::new();
+    let mut current = None;
+    for (c, style) in chars {
+        if *style != current {
+            if current.is_some() {
+                text.push_str(RESET);
+            }
+            text.push_str(style.unwrap_or_default());
+            current = *style;
+        }
+        text.push(*c);
+    }
+    if current.is_some() {
+        text.push_str(RESET);
+    }
+
+    text
+}
+
+/// Draw the chart of the entries of the Work, with a row for each day, in the given timezone, of
+/// the Range the Work was loaded for, like `timew day`, `timew week` and `timew month`.
+///
+/// The hours of the day are split in cells of `reports.<name>.cell` minutes, showing the tags of
+/// the entries tracked during that time, in their colours, and the excluded Ranges, shaded.
+/// Totals of each day and a summary of the tracked and available time can follow. The total of
+/// the summary is the time of the days that is not excluded.
+pub fn chart_in<Tz: TimeZone>(
+    work: &Work,
+    excluded: &[Range],
+    config: &ChartConfig,
+    tz: &Tz,
+) -> Result<String> {
+    let now = Utc::now();
+    let days = work_days(work, tz)?;
+    let entries: Vec<(&TimeEntry, Range)> = work
+        .entries()
+        .iter()
+        .filter_map(|e| {
+            let r = work.clipped(e)?;
+            Some((e, Range::new(r.from(), Some(r.to().unwrap_or(now))).ok()?))
+        })
+        .collect();
+
+    let mut assigned = BTreeMap::new();
+    let mut colors = vec![String::new(); entries.len()];
+    for (i, (entry, _)) in entries.iter().enumerate().rev() {
+        colors[i] = config.entry_color(entry, &mut assigned)?;
+    }
+
+    let cell_duration = Duration::minutes(config.cell as i64);
+    let per_hour = (60 / config.cell) as usize;
+    let mut grids = vec![];
+    for day in &days {
+        let midnight = day.and_time(NaiveTime::MIN);
+        let mut cells = vec![Cell::Empty; 24 * per_hour];
+        for (i, cell) in cells.iter_mut().enumerate() {
+            let start =
+                local_date_time(tz, midnight + cell_duration * i as i32)?.with_timezone(&Utc);
+            let end = start + cell_duration;
+            let overlaps = |r: &Range| r.from() < end && r.to().is_none_or(|t| t > start);
+            if let Some(e) = entries.iter().position(|(_, r)| overlaps(r)) {
+                *cell = Cell::Tracked(e);
+            } else if excluded.iter().any(overlaps) {
+                *cell = Cell::Excluded;
+            }
+        }
+        grids.push(cells);
+    }
+
+    let tracked: Vec<usize> = grids
+        .iter()
+        .flat_map(|cells| cells.iter().enumerate())
+        .filter(|(_, c)| matches!(c, Cell::Tracked(_)))
+        .map(|(i, _)| i / per_hour)
+        .collect();
+    let hours = match (tracked.iter().min(), tracked.iter().max()) {
+        (Some(first), Some(last)) if !config.all_hours => *first..*last + 1,
+        _ => 0..24,
+    };
+
+    let totals = work.by_day_in(tz)?;
+    let width = days
+        .first()
+        .map_or(0, |d| config.label(d, None).chars().count());
+    let indent = if width > 0 { width + 1 } else { 0 };
+    let mut chart = String::new();
+
+    if !config.internal_axis {
+        let axis: String = hours
+            .clone()
+            .map(|h| {
+                let w = per_hour + config.spacing;
+                format!("{:<w$}", h, w = w)
+                    .chars()
+                    .take(w)
+                    .collect::<String>()
+            })
+            .collect();
+        chart.push_str(format!("{}{}", " ".repeat(indent), axis).trim_end());
+        chart.push('\n');
+    }
+
+    for (d, day) in days.iter().enumerate() {
+        // The cells of the row with the spaces between the hours, that are drawn like the cells
+        // around them when both are the same, and the positions of the hours.
+        let grid = &grids[d];
+        let mut positions = vec![];
+        let mut hour_positions = vec![];
+        for h in hours.clone() {
+            hour_positions.push(positions.len());
+            positions.extend_from_slice(&grid[h * per_hour..(h + 1) * per_hour]);
+            if h + 1 < hours.end {
+                let (last, next) = (grid[(h + 1) * per_hour - 1], grid[(h + 1) * per_hour]);
+                let space = if last == next { last } else { Cell::Empty };
+                positions.extend(std::iter::repeat_n(space, config.spacing));
+            }
+        }
+
+        for line in 0..config.lines {
+            let mut chars: Vec<(char, Option<&str>)> = vec![];
+            let mut start = 0;
+            while start < positions.len() {
+                let cell = positions[start];
+                let len = positions[start..]
+                    .iter()
+                    .take_while(|c| **c == cell)
+                    .count();
+                match cell {
+                    Cell::Empty => chars.extend(std::iter::repeat_n((' ', None), len)),
+                    Cell::Excluded => {
+                        let style = Some(config.exclusion.as_str())
+                            .filter(|s| config.color && !s.is_empty());
+                        let fill = if config.color { ' ' } else { '.' };
+                        chars.extend(std::iter::repeat_n((fill, style), len));
+                    }
+                    Cell::Tracked(e) => {
+                        let text = entries[e].0.tags().join(" ");
+                        let style =
+                            Some(colors[e].as_str()).filter(|s| config.color && !s.is_empty());
+                        let fill = if config.color { ' ' } else { '+' };
+                        let text = text.chars().skip(line * len).chain(std::iter::repeat(fill));
+                        chars.extend(text.take(len).map(|c| (c, style)));
+                    }
+                }
+                start += len;
+            }
+
+            if config.internal_axis && line == 0 {
+                for (h, p) in hours.clone().zip(&hour_positions) {
+                    let end = (p + per_hour + config.spacing).min(chars.len());
+                    let label: Vec<char> = h.to_string().chars().collect();
+                    let free = p + label.len() <= end
+                        && positions[*p..p + label.len()]
+                            .iter()
+                            .all(|c| !matches!(c, Cell::Tracked(_)));
+                    if free {
+                        for (i, c) in label.into_iter().enumerate() {
+                            chars[p + i].0 = c;
+                        }
+                    }
+                }
+            }
+
+            let mut row = match (line, width) {
+                (_, 0) => String::new(),
+                (0, _) => format!("{} ", config.label(day, d.checked_sub(1).map(|p| &days[p]))),
+                _ => " ".repeat(indent),
+            };
+            row.push_str(&render_styled(&chars));
+            if config.totals && line == 0 {
+                let total = totals.get(day).copied().unwrap_or_else(Duration::zero);
+                if !total.is_zero() {
+                    row.push_str(&format!(
+                        " {:>8}",
+                        format_duration(&total, DurationFormat::Clock)
+                    ));
+                }
+            }
+            chart.push_str(row.trim_end());
+            chart.push('\n');
+        }
+    }
+
+    if config.summary {
+        let tracked = totals
+            .iter()
+            .filter(|(day, _)| days.contains(day))
+            .fold(Duration::zero(), |a, (_, d)| a + *d);
+        // The total is the time of the days that is not excluded, whether it is tracked or not,
+        // so the time tracked during exclusions is not in it.
+        let (available, total) = match (days.first(), days.last()) {
+            (Some(first), Some(last)) => {
+                let span = Range::new(
+                    Range::day_in(*first, tz)?.from(),
+                    Range::day_in(*last, tz)?.to(),
+                )?;
+                let gaps: RangeSet = work.gaps(&span, excluded).into_iter().collect();
+                let excluded: RangeSet = excluded.iter().copied().collect();
+                (
+                    gaps.duration(),
+                    RangeSet::from(&span).difference(&excluded).duration(),
+                )
+            }
+            _ => (Duration::zero(), Duration::zero()),
+        };
+
+        let lines = [
+            ("Tracked", tracked),
+            ("Available", available),
+            ("Total", total),
+        ]
+        .map(|(name, d)| (name, format_duration(&d, DurationFormat::Clock)));
+        let w = lines.iter().map(|(_, d)| d.len()).max().unwrap_or(0);
+        chart.push('\n');
+        for (name, d) in lines {
+            chart.push_str(&format!("{:<9} {:>w$}\n", name, d, w = w));
+        }
+    }
+
+    let holidays: Vec<_> = config
+        .holidays
+        .iter()
+        .filter(|(day, _)| days.binary_search(day).is_ok())
+        .collect();
+    if !holidays.is_empty() {
+        chart.push('\n');
+        for (day, name) in holidays {
+            chart.push_str(&format!("{} {}\n", day.format("%Y-%m-%d"), name));
+        }
+    }
+
+    Ok(chart)
+}
+
+// Draw the chart report of the given Range, or of the Range of the report, in local time.
+fn chart(report: ChartReport, range: Option<Range>) -> Result<String> {
+    let config = Config::load(&config::config_path()?)?;
+    let chart_config = ChartConfig::new(&config, report)?;
+    let range = match range {
+        Some(r) => r,
+        None => chart_config.range()?,
+    };
+
+    let excluded = config.exclusions()?.ranges(&range);
+    chart_in(&raw(Some(range))?, &excluded, &chart_config, &Local)
+}
+
+/// Draw the chart of `timew day` for the given Range, today by default. See `chart_in`.
+///
+/// The data is always retrieved from the `${HOME}/.timewarrior/data` file, with the configuration
+/// of `${HOME}/.timewarrior/timewarrior.cfg`.
+pub fn day(range: Option<Range>) -> Result<String> {
+    chart(ChartReport::Day, range)
+}
+
+/// Draw the chart of `timew week` for the given Range, the current week by default. See
+/// `chart_in`.
+///
+/// The data is always retrieved from the `${HOME}/.timewarrior/data` file, with the configuration
+/// of `${HOME}/.timewarrior/timewarrior.cfg`.
+pub fn week(range: Option<Range>) -> Result<String> {
+    chart(ChartReport::Week, range)
+}
+
+/// Draw the chart of `timew month` for the given Range, the current month by default. See
+/// `chart_in`.
+///
+/// The data is always retrieved from the `${HOME}/.timewarrior/data` file, with the configuration
+/// of `${HOME}/.timewarrior/timewarrior.cfg`.
+pub fn month(range: Option<Range>) -> Result<String> {
+    chart(ChartReport::Month, range)
+}
+
+/// The options of the summary report, set by the hints of `timew summary`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SummaryOptions {
+    ids: bool,
+    annotations: bool,
+    tags: bool,
+    holidays: bool,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> SummaryOptions {
+        SummaryOptions {
+            ids: false,
+            annotations: false,
+            tags: true,
+            holidays: false,
+        }
+    }
+}
+
+impl SummaryOptions {
+    /// Create the default options: only the Tags column is shown.
+    pub fn new() -> SummaryOptions {
+        SummaryOptions::default()
+    }
+
+    /// Parse hints such as `:ids`, `:annotations`, `:tags` and `:holidays`, that show the
+    /// column or the rows, or `:no-ids`, `:no-annotations`, `:no-tags` and `:no-holidays`,
+    /// that hide them.
+    pub fn from_hints<'a>(hints: impl IntoIterator<Item = &'a str>) -> Result<SummaryOptions> {
+        let mut options = SummaryOptions::default();
+        for hint in hints {
+            let name = match hint.strip_prefix(':') {
+                Some(name) => name,
+                None => bail!("Invalid hint '{}'", hint),
+            };
+            let (name, show) = match name.strip_prefix("no-") {
+                Some(name) => (name, false),
+                None => (name, true),
+            };
+            options = match name {
+                "ids" => options.ids(show),
+                "annotations" => options.annotations(show),
+                "tags" => options.tags(show),
+                "holidays" => options.holidays(show),
+                _ => bail!("Invalid hint '{}'", hint),
+            };
+        }
+
+        Ok(options)
+    }
+
+    /// Show the IDs of the entries
+    pub fn ids(mut self, show: bool) -> SummaryOptions {
+        self.ids = show;
+        self
+    }
+
+    /// Show the annotations of the entries
+    pub fn annotations(mut self, show: bool) -> SummaryOptions {
+        self.annotations = show;
+        self
+    }
+
+    /// Show the tags of the entries
+    pub fn tags(mut self, show: bool) -> SummaryOptions {
+        self.tags = show;
+        self
+    }
+
+    /// Show the holidays of the configuration, on the days of the summary
+    pub fn holidays(mut self, show: bool) -> SummaryOptions {
+        self.holidays = show;
+        self
+    }
+}
+
+/// A row of the summary: the part of an entry in a day.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SummaryRow {
+    id: usize,
+    tags: Vec<String>,
+    annotation: Option<String>,
+    start: NaiveTime,
+    end: Option<NaiveTime>,
+    time: Duration,
+}
+
+impl SummaryRow {
+    /// Return the ID of the entry.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Return the tags of the entry.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Return the annotation of the entry.
+    pub fn annotation(&self) -> Option<&str> {
+        self.annotation.as_deref()
+    }
+
+    /// Return the local time the entry starts at on the day, or midnight if it started before.
+    pub fn start(&self) -> NaiveTime {
+        self.start
+    }
+
+    /// Return the local time the entry ends at on the day, or midnight if it ends after. It is
+    /// `None` if the entry is still open.
+    pub fn end(&self) -> Option<NaiveTime> {
+        self.end
+    }
+
+    /// Return the time of the entry on the day.
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+}
+
+/// A day of the summary, with its entries from the oldest to the most recent one.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SummaryDay {
+    date: NaiveDate,
+    holiday: Option<String>,
+    rows: Vec<SummaryRow>,
+}
+
+impl SummaryDay {
+    /// Return the date of the day.
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    /// Return the ISO 8601 week number of the day.
+    pub fn week(&self) -> u32 {
+        self.date.iso_week().week()
+    }
+
+    /// Return the name of the holiday of the day, if it is one.
+    pub fn holiday(&self) -> Option<&str> {
+        self.holiday.as_deref()
+    }
+
+    /// Return the rows of the entries of the day.
+    pub fn rows(&self) -> &[SummaryRow] {
+        &self.rows
+    }
+
+    /// Return the time tracked on the day.
+    pub fn total(&self) -> Duration {
+        self.rows.iter().fold(Duration::zero(), |a, r| a + r.time)
+    }
+}
+
+/// The summary report of `timew summary`: the entries of each day, with their time and the
+/// total of each day.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Summary {
+    days: Vec<SummaryDay>,
+    options: SummaryOptions,
+}
+
+impl Summary {
+    /// Create the summary of the entries of the Work, in the given timezone. Entries that span
+    /// midnight are split between both days, and only the time in the Range the Work was loaded
+    /// for is shown. The holidays are shown on the days of the Range with the `holidays` option.
+    pub fn new_in<Tz: TimeZone>(
+        work: &Work,
+        options: SummaryOptions,
+        holidays: &BTreeMap<NaiveDate, String>,
+        tz: &Tz,
+    ) -> Result<Summary> {
+        let mut days: BTreeMap<NaiveDate, Vec<SummaryRow>> = BTreeMap::new();
+        for entry in work.entries() {
+            for (day, part) in work.entry_parts_in(entry, tz)? {
+                days.entry(day).or_default().push(SummaryRow {
+                    id: entry.id(),
+                    tags: entry.tags().to_vec(),
+                    annotation: entry.annotation().map(str::to_string),
+                    start: part.from().with_timezone(tz).time(),
+                    end: part.to().map(|t| t.with_timezone(tz).time()),
+                    time: part.duration(),
+                });
+            }
+        }
+
+        if options.holidays {
+            for day in work_days(work, tz)? {
+                if holidays.contains_key(&day) {
+                    days.entry(day).or_default();
+                }
+            }
+        }
+
+        Ok(Summary {
+            days: days
+                .into_iter()
+                .map(|(date, mut rows)| {
+                    rows.sort_by_key(|r| Reverse(r.id));
+                    SummaryDay {
+                        date,
+                        holiday: holidays.get(&date).filter(|_| options.holidays).cloned(),
+                        rows,
+                    }
+                })
+                .collect(),
+            options,
+        })
+    }
+
+    /// Create the summary of the entries of the Work, in local time
+    pub fn new(
+        work: &Work,
+        options: SummaryOptions,
+        holidays: &BTreeMap<NaiveDate, String>,
+    ) -> Result<Summary> {
+        Summary::new_in(work, options, holidays, &Local)
+    }
+
+    /// Return the days of the summary, from the oldest to the most recent one.
+    pub fn days(&self) -> &[SummaryDay] {
+        &self.days
+    }
+
+    /// Return the time tracked over all the days.
+    pub fn total(&self) -> Duration {
+        self.days
+            .iter()
+            .fold(Duration::zero(), |a, d| a + d.total())
+    }
+
+    /// Render the summary as the table of `timew summary`, with a grand total.
+    pub fn text(&self) -> String {
+        if self
+            .days
+            .iter()
+            .all(|d| d.rows.is_empty() && d.holiday.is_none())
+        {
+            return "No filtered data found.\n".to_string();
+        }
+
+        let clock = |d: &Duration| format_duration(d, DurationFormat::Clock);
+        let time = |t: &NaiveTime| t.format("%-H:%M:%S").to_string();
+        let o = &self.options;
+        // The columns, with whether they are shown and right aligned
+        let columns = [
+            ("Wk", true, false),
+            ("Date", true, false),
+            ("Day", true, false),
+            ("ID", o.ids, true),
+            ("Tags", o.tags, false),
+            ("Annotation", o.annotations, false),
+            ("Start", true, true),
+            ("End", true, true),
+            ("Time", true, true),
+            ("Total", true, true),
+        ];
+
+        // The cells of each row, and the holiday shown after the day on its first row
+        let mut table: Vec<(Vec<String>, Option<&str>)> = vec![];
+        for day in &self.days {
+            let first = vec![
+                format!("W{}", day.week()),
+                day.date.format("%Y-%m-%d").to_string(),
+                day.date.format("%a").to_string(),
+            ];
+            if let Some(holiday) = &day.holiday {
+                table.push((first.clone(), Some(holiday)));
+            }
+
+            for (i, row) in day.rows.iter().enumerate() {
+                let mut cells = match (i, &day.holiday) {
+                    (0, None) => first.clone(),
+                    _ => vec![String::new(); 3],
+                };
+                cells.push(format!("@{}", row.id));
+                cells.push(row.tags.join(", "));
+                cells.push(row.annotation.clone().unwrap_or_default());
+                cells.push(time(&row.start));
+                cells.push(row.end.as_ref().map_or("-".to_string(), time));
+                cells.push(clock(&row.time));
+                if i + 1 == day.rows.len() {
+                    cells.push(clock(&day.total()));
+                }
+                table.push((cells, None));
+            }
+        }
+
+        // Only the cells of the columns shown are rendered.
+        let visible: Vec<usize> = (0..columns.len()).filter(|i| columns[*i].1).collect();
+        let shown = |cells: &[String]| -> Vec<String> {
+            visible
+                .iter()
+                .map(|i| cells.get(*i).cloned().unwrap_or_default())
+                .collect()
+        };
+        let footer = |last: String| {
+            let mut cells = vec![String::new(); 9];
+            cells.push(last);
+            shown(&cells)
+        };
+        let header = shown(&columns.map(|c| c.0.to_string()));
+        let total = footer(clock(&self.total()));
+        let mut rows = vec![header.clone(), total.clone()];
+        rows.extend(table.iter().map(|(cells, _)| shown(cells)));
+        let right = visible.iter().map(|i| columns[*i].2).collect();
+        let text_table = TextTable::new(" ", right, &rows);
+
+        let mut text = String::from("\n");
+        text.push_str(&text_table.line(&header));
+        text.push('\n');
+        text.push_str(&text_table.rule());
+        text.push('\n');
+        for (cells, holiday) in &table {
+            text.push_str(&text_table.line(&shown(cells)));
+            if let Some(holiday) = holiday {
+                text.push_str(&format!(" {}", holiday));
+            }
+            text.push('\n');
+        }
+        // The Total column is always shown, and it is the last one
+        let rule = footer("-".repeat(text_table.widths.last().copied().unwrap_or(0)));
+        text.push_str(&text_table.line(&rule));
+        text.push('\n');
+        text.push_str(&text_table.line(&total));
+        text.push('\n');
+
+        text
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+/// Get the summary of the entries matching the filter, like `timew summary`. See
+/// `Summary::new_in`.
+///
+/// The data is always retrieved from the `${HOME}/.timewarrior/data` file, with the holidays of
+/// `${HOME}/.timewarrior/timewarrior.cfg`.
+pub fn summary(filter: &Filter, options: SummaryOptions) -> Result<Summary> {
+    let config = Config::load(&config::config_path()?)?;
+    Summary::new(&filtered(filter)?, options, &config.holidays()?)
+}
+
+/// A tag of the tags report.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TagInfo {
+    name: String,
+    description: Option<String>,
+    color: Option<String>,
+    entries: usize,
+    uses: u64,
+    time: Duration,
+    last_used: Option<NaiveDate>,
+}
+
+impl TagInfo {
+    /// Return the name of the tag.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the description of the tag, from `tags.<tag>.description` in the configuration.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Return the colour of the tag, from `tags.<tag>.color` in the configuration.
+    pub fn color(&self) -> Option<&str> {
+        self.color.as_deref()
+    }
+
+    /// Return the number of entries with the tag.
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    /// Return the usage count of the tag in the whole database, from `tags.data`.
+    pub fn uses(&self) -> u64 {
+        self.uses
+    }
+
+    /// Return the time tracked with the tag. The time of the entries with several tags is counted
+    /// for each of them.
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// Return the day the most recent entry with the tag started.
+    pub fn last_used(&self) -> Option<NaiveDate> {
+        self.last_used
+    }
+}
+
+/// The tags report: the tags of the entries, with their settings and their use.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TagsReport {
+    tags: Vec<TagInfo>,
+}
+
+impl TagsReport {
+    /// Create the report of the tags of the entries of the Work, with the days in the given
+    /// timezone. Only the time in the Range the Work was loaded for is counted. When the Work was
+    /// loaded without Range, the tags of `tags.data` and of the configuration are listed too, even
+    /// if no entry has them.
+    pub fn new_in<Tz: TimeZone>(
+        work: &Work,
+        tags_data: &TagsData,
+        config: &Config,
+        tz: &Tz,
+    ) -> TagsReport {
+        let mut names: BTreeSet<String> = work
+            .entries()
+            .iter()
+            .flat_map(|e| e.tags().iter().cloned())
+            .collect();
+        if work.range().is_none() {
+            names.extend(tags_data.tags().map(|(t, _)| t.to_string()));
+            names.extend(config.tags());
+        }
+
+        let times = work.by_tag(TagAttribution::Full);
+        let setting = |tag: &str, key: &str| config.get(&format!("tags.{}.{}", tag, key));
+        let tags = names
+            .into_iter()
+            .map(|name| {
+                let entries: Vec<&TimeEntry> = work
+                    .entries()
+                    .iter()
+                    .filter(|e| e.tags().contains(&name))
+                    .collect();
+                TagInfo {
+                    description: setting(&name, "description").map(str::to_string),
+                    color: setting(&name, "color").map(str::to_string),
+                    entries: entries.len(),
+                    uses: tags_data.count(&name),
+                    time: times.get(&name).copied().unwrap_or_else(Duration::zero),
+                    last_used: entries
+                        .iter()
+                        .map(|e| e.range().from().with_timezone(tz).date_naive())
+                        .max(),
+                    name,
+                }
+            })
+            .collect();
+
+        TagsReport { tags }
+    }
+
+    /// Create the report of the tags of the entries of the Work, with the local days
+    pub fn new(work: &Work, tags_data: &TagsData, config: &Config) -> TagsReport {
+        TagsReport::new_in(work, tags_data, config, &Local)
+    }
+
+    /// Return the tags, sorted by name.
+    pub fn tags(&self) -> &[TagInfo] {
+        &self.tags
+    }
+
+    /// Render the report as a text table.
+    pub fn text(&self) -> String {
+        if self.tags.is_empty() {
+            return "No data found.\n".to_string();
+        }
+
+        let header = [
+            "Tag",
+            "Description",
+            "Color",
+            "Entries",
+            "Uses",
+            "Time",
+            "Last used",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        let mut table = vec![header];
+        for tag in &self.tags {
+            table.push(vec![
+                tag.name.clone(),
+                tag.description.clone().unwrap_or_default(),
+                tag.color.clone().unwrap_or_default(),
+                tag.entries.to_string(),
+                tag.uses.to_string(),
+                format_duration(&tag.time, DurationFormat::Clock),
+                tag.last_used
+                    .map(|d| d.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+            ]);
+        }
+        // The Entries, Uses and Time columns are right aligned
+        let right = (0..table[0].len()).map(|i| (3..6).contains(&i)).collect();
+        let text_table = TextTable::new(" ", right, &table);
+
+        let mut text = String::from("\n");
+        text.push_str(&text_table.line(&table[0]));
+        text.push('\n');
+        text.push_str(&text_table.rule());
+        text.push('\n');
+        for cells in &table[1..] {
+            text.push_str(&text_table.line(cells));
+            text.push('\n');
+        }
+
+        text
+    }
+}
+
+impl Display for TagsReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+/// Get the report of the tags used in the given Range, like `timew tags`. Without Range, every
+/// known tag is listed. See `TagsReport::new_in`.
+///
+/// The data is always retrieved from the `${HOME}/.timewarrior/data` file, with the tag settings
+/// of `${HOME}/.timewarrior/timewarrior.cfg`.
+pub fn tags(range: Option<Range>) -> Result<TagsReport> {
+    let data_path = config::data_path()?;
+    let config = Config::load(&config::config_path()?)?;
+    Ok(TagsReport::new(
+        &Work::load_range(&data_path, range)?,
+        &TagsData::load(&data_path)?,
+        &config,
+    ))
+}
+
+#[cfg(test)]
+mod formatter_tests {
+    use crate::config::Config;
+    use crate::data::{Range, TagAttribution, TagsData, TestDir, TimeEntry, Work};
+    use crate::filter::TagPattern;
+    use crate::formatter::{
+        ansi_color, chart_in, ChartConfig, ChartReport, Summary, SummaryOptions, TagGroup,
+        TagsReport, Timesheet, RESET,
+    };
+    use chrono::{Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    fn work(name: &str, range: &str) -> Work {
+        let path = TestDir::new(&format!("formatter-{}", name));
+        fs::write(
+            path.join("2022-07.data"),
+            "inc 20220710T230000Z - 20220711T010000Z # a\n\
+             inc 20220711T090000Z - 20220711T100000Z # a b # \"review\"\n\
+             inc 20220712T090000Z - 20220712T103000Z # proj-x proj-y\n\
+             inc 20220712T140000Z - 20220712T150000Z\n\
+             inc 20220713T090000Z - 20220713T100000Z # c\n",
+        )
+        .unwrap();
+
+        Work::load_range(&path, range.parse().ok()).unwrap()
+    }
+
+    #[test]
+    fn test_timesheet() {
+        let work = work("timesheet", "20220711T000000Z - 20220713T000000Z");
+        let groups = [TagGroup::new(
+            "project",
+            [TagPattern::glob("proj-*").unwrap()],
+        )];
+        let sheet = Timesheet::new_in(&work, &groups, TagAttribution::Split, &Utc).unwrap();
+
+        let hours = |h: i64| Duration::minutes(h * 60);
+        assert_eq!(
+            sheet.days(),
+            [
+                NaiveDate::from_ymd_opt(2022, 7, 11).unwrap(),
+                NaiveDate::from_ymd_opt(2022, 7, 12).unwrap()
+            ]
+        );
+        let rows: Vec<(&str, &[Duration], Duration)> = sheet
+            .rows()
+            .iter()
+            .map(|r| (r.label(), r.days(), r.total()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("", &[hours(0), hours(1)][..], hours(1)),
+                (
+                    "a",
+                    &[Duration::minutes(90), hours(0)][..],
+                    Duration::minutes(90)
+                ),
+                (
+                    "b",
+                    &[Duration::minutes(30), hours(0)][..],
+                    Duration::minutes(30)
+                ),
+                (
+                    "project",
+                    &[hours(0), Duration::minutes(90)][..],
+                    Duration::minutes(90)
+                ),
+            ]
+        );
+        assert_eq!(sheet.totals(), [hours(2), Duration::minutes(150)]);
+        assert_eq!(sheet.total(), Duration::minutes(270));
+
+        assert_eq!(
+            sheet.text(),
+            "Tag      Mon 07-11  Tue 07-12    Total\n\
+             -------  ---------  ---------  -------\n\
+             \x20                     1:00:00  1:00:00\n\
+             a          1:30:00             1:30:00\n\
+             b          0:30:00             0:30:00\n\
+             project               1:30:00  1:30:00\n\
+             -------  ---------  ---------  -------\n\
+             Total      2:00:00    2:30:00  4:30:00\n"
+        );
+        assert_eq!(
+            sheet.csv(),
+            "Tag,2022-07-11,2022-07-12,Total\n\
+             ,0.00,1.00,1.00\n\
+             a,1.50,0.00,1.50\n\
+             b,0.50,0.00,0.50\n\
+             project,0.00,1.50,1.50\n\
+             Total,2.00,2.50,4.50\n"
+        );
+        assert_eq!(
+            sheet.markdown(),
+            "| Tag | Mon 07-11 | Tue 07-12 | Total |\n\
+             |:--|--:|--:|--:|\n\
+             |  |  | 1:00:00 | 1:00:00 |\n\
+             | a | 1:30:00 |  | 1:30:00 |\n\
+             | b | 0:30:00 |  | 0:30:00 |\n\
+             | project |  | 1:30:00 | 1:30:00 |\n\
+             | Total | 2:00:00 | 2:30:00 | 4:30:00 |\n"
+        );
+
+        let sheet = Timesheet::new_in(&work, &[], TagAttribution::Full, &Utc).unwrap();
+        let labels: Vec<&str> = sheet.rows().iter().map(|r| r.label()).collect();
+        assert_eq!(labels, ["", "a", "b", "proj-x", "proj-y"]);
+        assert_eq!(sheet.rows()[1].total(), hours(2));
+        assert_eq!(sheet.total(), Duration::minutes(270));
+    }
+
+    #[test]
+    fn test_timesheet_without_range() {
+        let work = work("timesheet-all", "");
+        let sheet = Timesheet::new_in(&work, &[], TagAttribution::First, &Utc).unwrap();
+        assert_eq!(sheet.days().len(), 4);
+        assert_eq!(sheet.totals()[0], Duration::hours(1));
+        assert_eq!(sheet.total(), Duration::minutes(390));
+    }
+
+    #[test]
+    fn test_chart() {
+        let work = work("chart", "20220711T000000Z - 20220713T000000Z");
+        let config: Config = "color = off\n\
+                              reports.week.cell = 30\n\
+                              reports.week.hours = auto\n\
+                              reports.week.totals = yes\n\
+                              holidays.fr-FR.2022_07_12 = Holiday\n"
+            .parse()
+            .unwrap();
+        let excluded: Vec<Range> = [
+            "20220711T000000Z - 20220711T080000Z",
+            "20220712T120000Z - 20220712T130000Z",
+        ]
+        .iter()
+        .map(|r| r.parse().unwrap())
+        .collect();
+
+        // The hour tracked during the exclusion of Monday morning is not in the total
+        let chart_config = ChartConfig::new(&config, ChartReport::Week).unwrap();
+        assert_eq!(
+            chart_in(&work, &excluded, &chart_config, &Utc).unwrap(),
+            "Jul W28 Mon 11 a+ 1..2..3..4..5..6..7. 8  a  10 11 12 13 14  2:00:00\n\
+             \x20       Tue 12 0  1  2  3  4  5  6  7  8  proj  11 12 13 ++  2:30:00\n\
+             \n\
+             Tracked    4:30:00\n\
+             Available 35:30:00\n\
+             Total     39:00:00\n\
+             \n\
+             2022-07-12 Holiday\n"
+        );
+
+        let config: Config = "reports.day.axis = external\n\
+                              reports.day.summary = no\n\
+                              reports.day.month = no\n\
+                              reports.day.week = no\n\
+                              tags.b.color = white on blue\n"
+            .parse()
+            .unwrap();
+        let chart_config = ChartConfig::new(&config, ChartReport::Day).unwrap();
+        let chart = chart_in(&work, &excluded, &chart_config, &Utc).unwrap();
+        let lines: Vec<&str> = chart.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("       0    1    2"));
+        assert!(lines[0].ends_with("22   23"));
+        assert_eq!(
+            lines[1],
+            format!(
+                "Mon 11 {}a   {} {}{}{}      {}a b {}",
+                "\x1b[38;5;0;48;5;1m",
+                RESET,
+                "\x1b[38;5;240;48;5;236m",
+                " ".repeat(34),
+                RESET,
+                "\x1b[38;5;7;48;5;4m",
+                RESET
+            )
+        );
+        assert!(lines[2].starts_with(&format!("Tue 12 {}", " ".repeat(45))));
+
+        assert!(
+            ChartConfig::new(&"reports.day.cell = 7".parse().unwrap(), ChartReport::Day).is_err()
+        );
+        assert!(ChartConfig::new(
+            &"reports.month.hours = some".parse().unwrap(),
+            ChartReport::Month
+        )
+        .is_err());
+        assert!(
+            ChartConfig::new(&"tags.a.color = pink".parse().unwrap(), ChartReport::Day)
+                .and_then(|c| chart_in(&work, &[], &c, &Utc))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_ansi_color() {
+        assert_eq!(ansi_color("").unwrap(), "");
+        assert_eq!(ansi_color("red").unwrap(), "\x1b[38;5;1m");
+        assert_eq!(
+            ansi_color("bold bright white on rgb012").unwrap(),
+            "\x1b[1;38;5;15;48;5;24m"
+        );
+        assert_eq!(
+            ansi_color("color200 on gray23").unwrap(),
+            "\x1b[38;5;200;48;5;255m"
+        );
+        assert!(ansi_color("gray24").is_err());
+        assert!(ansi_color("rgb600").is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let work = work("summary", "20220711T000000Z - 20220713T000000Z");
+        let holidays = "holidays.fr-FR.2022_07_12 = Holiday"
+            .parse::<Config>()
+            .unwrap()
+            .holidays()
+            .unwrap();
+
+        let summary = Summary::new_in(&work, SummaryOptions::new(), &holidays, &Utc).unwrap();
+        assert_eq!(summary.days().len(), 2);
+        let day = &summary.days()[0];
+        assert_eq!(day.date(), NaiveDate::from_ymd_opt(2022, 7, 11).unwrap());
+        assert_eq!(day.week(), 28);
+        assert_eq!(day.holiday(), None);
+        assert_eq!(day.total(), Duration::hours(2));
+        let row = &day.rows()[1];
+        assert_eq!(
+            (row.id(), row.tags(), row.annotation()),
+            (4, &["a".to_string(), "b".to_string()][..], Some("review"))
+        );
+        assert_eq!(
+            (row.start(), row.end(), row.time()),
+            (
+                NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(10, 0, 0),
+                Duration::hours(1)
+            )
+        );
+        assert_eq!(summary.total(), Duration::minutes(270));
+        assert_eq!(
+            summary.to_string(),
+            "\n\
+             Wk  Date       Day Tags              Start      End    Time   Total\n\
+             --- ---------- --- -------------- -------- -------- ------- -------\n\
+             W28 2022-07-11 Mon a               0:00:00  1:00:00 1:00:00\n\
+             \x20                  a, b            9:00:00 10:00:00 1:00:00 2:00:00\n\
+             W28 2022-07-12 Tue proj-x, proj-y  9:00:00 10:30:00 1:30:00\n\
+             \x20                                 14:00:00 15:00:00 1:00:00 2:30:00\n\
+             \x20                                                           -------\n\
+             \x20                                                           4:30:00\n"
+        );
+
+        let options =
+            SummaryOptions::from_hints([":ids", ":annotations", ":no-tags", ":holidays"]).unwrap();
+        let summary = Summary::new_in(&work, options, &holidays, &Utc).unwrap();
+        assert_eq!(summary.days()[1].holiday(), Some("Holiday"));
+        assert_eq!(
+            summary.text(),
+            "\n\
+             Wk  Date       Day ID Annotation    Start      End    Time   Total\n\
+             --- ---------- --- -- ---------- -------- -------- ------- -------\n\
+             W28 2022-07-11 Mon @5             0:00:00  1:00:00 1:00:00\n\
+             \x20                  @4 review      9:00:00 10:00:00 1:00:00 2:00:00\n\
+             W28 2022-07-12 Tue Holiday\n\
+             \x20                  @3             9:00:00 10:30:00 1:30:00\n\
+             \x20                  @2            14:00:00 15:00:00 1:00:00 2:30:00\n\
+             \x20                                                          -------\n\
+             \x20                                                          4:30:00\n"
+        );
+
+        assert!(SummaryOptions::from_hints(["ids"]).is_err());
+        assert!(SummaryOptions::from_hints([":week"]).is_err());
+        let empty = Summary::new_in(
+            &self::work("summary-empty", "20220720T000000Z - 20220721T000000Z"),
+            options,
+            &holidays,
+            &Utc,
+        )
+        .unwrap();
+        assert!(empty.days().is_empty());
+        assert_eq!(empty.text(), "No filtered data found.\n");
+    }
+
+    #[test]
+    fn test_summary_open() {
+        let path = TestDir::new("formatter-summary-open");
+        let now = Utc::now();
+        let from = Utc.timestamp_opt(now.timestamp() - 1800, 0).unwrap();
+        let entry = TimeEntry::new(Range::new(from, None).unwrap(), vec!["a".to_string()], None);
+        fs::write(
+            path.join(entry.data_file_name()),
+            format!("{}\n", entry.serialize()),
+        )
+        .unwrap();
+
+        // The Range ends in the future, but the open entry is only counted until now
+        let range = Range::new(now - Duration::hours(1), Some(now + Duration::hours(1))).unwrap();
+        let work = Work::load_range(&path, Some(range)).unwrap();
+
+        let summary =
+            Summary::new_in(&work, SummaryOptions::new(), &BTreeMap::new(), &Utc).unwrap();
+        let row = summary.days().last().unwrap().rows().last().unwrap();
+        assert_eq!(row.end(), None);
+        assert!(summary.total() >= Duration::minutes(30));
+        assert!(summary.total() < Duration::minutes(31));
+        let text = summary.text();
+        let line = text.lines().find(|l| l.contains(" a ")).unwrap();
+        assert!(line.contains(" - "), "{}", line);
+    }
+
+    #[test]
+    fn test_tags_report() {
+        let path = TestDir::new("formatter-tags-report");
+        fs::write(
+            path.join("tags.data"),
+            "{\n  \"a\":{\"count\":3},\n  \"old\":{\"count\":1}\n}",
+        )
+        .unwrap();
+        let tags_data = TagsData::load(&path).unwrap();
+
+        let config: Config = "define tags:\n\
+                              \x20 a:\n\
+                              \x20   description = Project A\n\
+                              \x20   color = white on blue\n\
+                              \x20 planned:\n\
+                              \x20   description = Not started\n"
+            .parse()
+            .unwrap();
+
+        let report = TagsReport::new_in(
+            &work("tags", "20220711T000000Z - 20220713T000000Z"),
+            &tags_data,
+            &config,
+            &Utc,
+        );
+        let tag = &report.tags()[0];
+        assert_eq!(
+            (tag.name(), tag.description(), tag.color()),
+            ("a", Some("Project A"), Some("white on blue"))
+        );
+        assert_eq!((tag.entries(), tag.uses()), (2, 3));
+        assert_eq!(tag.time(), Duration::hours(2));
+        assert_eq!(tag.last_used(), NaiveDate::from_ymd_opt(2022, 7, 11));
+        assert_eq!(
+            report.to_string(),
+            "\n\
+             Tag    Description Color         Entries Uses    Time Last used\n\
+             ------ ----------- ------------- ------- ---- ------- ----------\n\
+             a      Project A   white on blue       2    3 2:00:00 2022-07-11\n\
+             b                                      1    0 1:00:00 2022-07-11\n\
+             proj-x                                 1    0 1:30:00 2022-07-12\n\
+             proj-y                                 1    0 1:30:00 2022-07-12\n"
+        );
+
+        let report = TagsReport::new_in(&work("tags-all", ""), &tags_data, &config, &Utc);
+        let names: Vec<&str> = report.tags().iter().map(|t| t.name()).collect();
+        assert_eq!(names, ["a", "b", "c", "old", "planned", "proj-x", "proj-y"]);
+        assert_eq!(report.tags()[0].time(), Duration::hours(3));
+        assert_eq!(
+            report.tags()[0].last_used(),
+            NaiveDate::from_ymd_opt(2022, 7, 11)
+        );
+        assert_eq!(report.tags()[3].entries(), 0);
+        assert_eq!(report.tags()[3].uses(), 1);
+        assert_eq!(report.tags()[4].description(), Some("Not started"));
 
-    Work::load_range(&data_path, range)
+        let report = TagsReport::new_in(
+            &work("tags-empty", "20220720T000000Z - 20220721T000000Z"),
+            &tags_data,
+            &config,
+            &Utc,
+        );
+        assert_eq!(report.text(), "No data found.\n");
+    }
 }
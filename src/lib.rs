@@ -9,13 +9,15 @@
 //! Even future work will allow adding entries in the database.
 //! Usage example:
 //!
-//!     use timewarrior_rs::{ data, formatter };
-//!     let range = data::Range::today().unwrap();
-//!     let work = formatter::raw(Some(range)).unwrap();
-//!     println!("{}", data::Range::pretty_duration(&work.duration()));
-//!     for entry in work.entries() {
-//!         println!("{entry}");
-//!     }
+//! ```no_run
+//! use timewarrior_rs::{ data, formatter };
+//! let range = data::Range::today().unwrap();
+//! let work = formatter::raw(Some(range)).unwrap();
+//! println!("{}", data::Range::pretty_duration(&work.duration()));
+//! for entry in work.entries() {
+//!     println!("{entry}");
+//! }
+//! ```
 
 /// Represent data as fetched from the database
 pub mod data;
@@ -24,4 +26,20 @@ pub mod data;
 pub mod formatter;
 
 pub mod config;
+
+/// Parse the date/time and interval expressions of timew
+pub mod date;
+
+/// Parse and format durations
+pub mod duration;
+
 pub mod editor;
+
+/// Select time entries by range and tags
+pub mod filter;
+
+/// Set operations on ranges of time
+pub mod rangeset;
+
+/// Check the consistency of the database
+pub mod validation;
@@ -0,0 +1,238 @@
+// This file contains the RangeSet, that computes unions, intersections and differences of Ranges.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::data::Range;
+
+// The end of open Ranges, that never end in a RangeSet.
+const NEVER: DateTime<Utc> = DateTime::<Utc>::MAX_UTC;
+
+/// A set of times, stored as sorted Ranges that neither overlap nor touch each other.
+///
+/// Each Range contains its start but not its end, so that adjacent Ranges such as 9:00 - 10:00 and
+/// 10:00 - 11:00 are merged into 9:00 - 11:00. Open Ranges never end.
+///
+/// As the Ranges are merged, a RangeSet tells whether times are tracked or excluded, but not by
+/// which entries. Checks that report the overlapping entries, such as `validation::validate`,
+/// compare the entries themselves instead.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct RangeSet {
+    // The start and end of each Range, the end of open Ranges being NEVER.
+    bounds: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl RangeSet {
+    /// Create an empty RangeSet
+    pub fn new() -> RangeSet {
+        RangeSet::default()
+    }
+
+    // Create a RangeSet from bounds in any order, that may overlap.
+    fn from_bounds(mut bounds: Vec<(DateTime<Utc>, DateTime<Utc>)>) -> RangeSet {
+        bounds.retain(|(from, to)| from < to);
+        bounds.sort();
+
+        let mut merged: Vec<(DateTime<Utc>, DateTime<Utc>)> = vec![];
+        for (from, to) in bounds {
+            match merged.last_mut() {
+                Some(last) if from <= last.1 => last.1 = last.1.max(to),
+                _ => merged.push((from, to)),
+            }
+        }
+
+        RangeSet { bounds: merged }
+    }
+
+    /// Add a Range to the set
+    pub fn insert(&mut self, range: &Range) {
+        let mut bounds = std::mem::take(&mut self.bounds);
+        bounds.push((range.from(), range.to().unwrap_or(NEVER)));
+        *self = RangeSet::from_bounds(bounds);
+    }
+
+    /// Return the Ranges of the set, from the oldest to the most recent. Parts of the set that last
+    /// less than a second are left out, as a Range cannot be that short.
+    pub fn ranges(&self) -> Vec<Range> {
+        self.bounds
+            .iter()
+            .filter_map(|(from, to)| Range::new(*from, (*to != NEVER).then_some(*to)).ok())
+            .collect()
+    }
+
+    /// Return true if the set contains no time
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+
+    /// Return true if the given date/time is in the set
+    pub fn contains(&self, time: DateTime<Utc>) -> bool {
+        self.bounds
+            .iter()
+            .any(|(from, to)| *from <= time && time < *to)
+    }
+
+    /// Return the times that are in this set or in the other one
+    pub fn union(&self, other: &RangeSet) -> RangeSet {
+        RangeSet::from_bounds([&self.bounds[..], &other.bounds[..]].concat())
+    }
+
+    /// Return the times that are both in this set and in the other one
+    pub fn intersection(&self, other: &RangeSet) -> RangeSet {
+        let mut bounds = vec![];
+        let (mut i, mut j) = (0, 0);
+        while i < self.bounds.len() && j < other.bounds.len() {
+            let (a, b) = (self.bounds[i], other.bounds[j]);
+            bounds.push((a.0.max(b.0), a.1.min(b.1)));
+            if a.1 < b.1 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        RangeSet::from_bounds(bounds)
+    }
+
+    /// Return the times that are in this set but not in the other one
+    pub fn difference(&self, other: &RangeSet) -> RangeSet {
+        self.intersection(&other.complement_within(DateTime::<Utc>::MIN_UTC, NEVER))
+    }
+
+    /// Return the times of the given Range that are not in the set
+    pub fn complement(&self, bounds: &Range) -> RangeSet {
+        self.complement_within(bounds.from(), bounds.to().unwrap_or(NEVER))
+    }
+
+    fn complement_within(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> RangeSet {
+        let mut bounds = vec![];
+        let mut current = start;
+        for (from, to) in &self.bounds {
+            bounds.push((current, (*from).min(end)));
+            current = current.max(*to);
+        }
+        bounds.push((current, end));
+
+        RangeSet::from_bounds(bounds)
+    }
+
+    /// Return the total duration of the set. Open Ranges end now, like in `Range::duration`.
+    pub fn duration(&self) -> Duration {
+        let now = Utc::now();
+        self.bounds
+            .iter()
+            .map(|(from, to)| {
+                if *to == NEVER {
+                    now - *from
+                } else {
+                    *to - *from
+                }
+            })
+            .fold(Duration::zero(), |a, d| a + d)
+    }
+}
+
+impl FromIterator<Range> for RangeSet {
+    fn from_iter<I: IntoIterator<Item = Range>>(ranges: I) -> RangeSet {
+        RangeSet::from_bounds(
+            ranges
+                .into_iter()
+                .map(|r| (r.from(), r.to().unwrap_or(NEVER)))
+                .collect(),
+        )
+    }
+}
+
+impl From<&Range> for RangeSet {
+    fn from(range: &Range) -> RangeSet {
+        RangeSet::from_iter([*range])
+    }
+}
+
+#[cfg(test)]
+mod rangeset_tests {
+    use crate::data::Range;
+    use crate::rangeset::RangeSet;
+    use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+
+    fn parse_date_time(date: &str) -> DateTime<Utc> {
+        let d = NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ").unwrap();
+        Utc.from_utc_datetime(&d)
+    }
+
+    // Create a RangeSet from ranges of hours of 11 July 2022, in UTC. A range without end is open.
+    fn set(hours: &[(u32, Option<u32>)]) -> RangeSet {
+        hours.iter().map(|(from, to)| range(*from, *to)).collect()
+    }
+
+    fn range(from: u32, to: Option<u32>) -> Range {
+        let hour = |h: u32| parse_date_time(&format!("20220711T{:02}0000Z", h));
+        Range::new(hour(from), to.map(hour)).unwrap()
+    }
+
+    #[test]
+    fn test_rangeset_normalize() {
+        let s = set(&[
+            (14, Some(15)),
+            (9, Some(10)),
+            (10, Some(11)),
+            (9, Some(10)),
+            (16, None),
+        ]);
+        assert_eq!(
+            s.ranges(),
+            vec![range(9, Some(11)), range(14, Some(15)), range(16, None)]
+        );
+        assert!(s.contains(range(10, None).from()));
+        assert!(!s.contains(range(11, None).from()));
+        assert!(s.contains(range(23, None).from()));
+
+        let mut s = set(&[(9, Some(10)), (12, Some(13))]);
+        s.insert(&range(8, Some(12)));
+        assert_eq!(s.ranges(), vec![range(8, Some(13))]);
+        s.insert(&range(10, None));
+        assert_eq!(s.ranges(), vec![range(8, None)]);
+
+        assert!(RangeSet::new().is_empty());
+        assert_eq!(
+            RangeSet::from(&range(9, Some(10))).duration(),
+            Duration::hours(1)
+        );
+    }
+
+    #[test]
+    fn test_rangeset_operations() {
+        let a = set(&[(8, Some(10)), (12, Some(14)), (16, None)]);
+        let b = set(&[(9, Some(13)), (17, Some(18))]);
+
+        assert_eq!(
+            a.union(&b).ranges(),
+            vec![range(8, Some(14)), range(16, None)]
+        );
+        assert_eq!(
+            a.intersection(&b).ranges(),
+            vec![range(9, Some(10)), range(12, Some(13)), range(17, Some(18))]
+        );
+        assert_eq!(
+            a.difference(&b).ranges(),
+            vec![
+                range(8, Some(9)),
+                range(13, Some(14)),
+                range(16, Some(17)),
+                range(18, None)
+            ]
+        );
+        assert_eq!(b.difference(&a).ranges(), vec![range(10, Some(12))]);
+        assert_eq!(
+            a.complement(&range(7, Some(20))).ranges(),
+            vec![range(7, Some(8)), range(10, Some(12)), range(14, Some(16))]
+        );
+        assert_eq!(
+            b.complement(&range(10, None)).ranges(),
+            vec![range(13, Some(17)), range(18, None)]
+        );
+        assert!(a.complement(&range(16, None)).is_empty());
+        assert_eq!(a.intersection(&b).duration(), Duration::hours(3));
+        assert!(a.intersection(&RangeSet::new()).is_empty());
+        assert_eq!(a.difference(&RangeSet::new()), a);
+    }
+}
@@ -0,0 +1,560 @@
+// This file contains the functions that check the consistency of the database and repair it.
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use std::ffi::OsStr;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nom::bytes::complete::tag;
+use nom::sequence::{preceded, separated_pair};
+
+use crate::data::{data_files, parse_date, parse_line, Location, Range, TimeEntry, Work};
+
+/// A problem found in the database.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Problem {
+    /// The line cannot be parsed as an entry.
+    Unparseable,
+    /// The entry ends before it starts, or lasts less than a second.
+    EmptyRange,
+    /// The entry is not in the data file of the month it starts in, which is given.
+    Misfiled(String),
+    /// The entry is open, but it is not the most recent open entry.
+    ExtraOpen,
+    /// The entry overlaps the entry at the given location.
+    Overlap(Location),
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Unparseable => write!(f, "cannot parse the line"),
+            Problem::EmptyRange => write!(f, "the entry ends before it starts"),
+            Problem::Misfiled(file) => write!(f, "the entry belongs in {}", file),
+            Problem::ExtraOpen => write!(f, "the entry is open but is not the last open one"),
+            Problem::Overlap(other) => write!(f, "the entry overlaps {}", other),
+        }
+    }
+}
+
+/// A problem found at a location of the database.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Finding {
+    location: Location,
+    problem: Problem,
+}
+
+impl Finding {
+    /// Return the location of the line with the problem.
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    /// Return the problem found.
+    pub fn problem(&self) -> &Problem {
+        &self.problem
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.problem)
+    }
+}
+
+// Return true if the line is an entry with a range that ends before it starts.
+fn is_empty_range(line: &str) -> bool {
+    let range = preceded(
+        tag("inc "),
+        separated_pair(parse_date, tag(" - "), parse_date),
+    )(line);
+
+    matches!(range, Ok((_, (from, to))) if to <= from)
+}
+
+/// Check the timewarrior database at data_path and return the problems found, sorted by location.
+///
+/// Each line of the data files is checked, so a line that cannot be parsed does not prevent the
+/// other lines from being checked.
+pub fn validate(data_path: &Path) -> Result<Vec<Finding>> {
+    let mut findings = vec![];
+    let mut entries: Vec<(Location, TimeEntry)> = vec![];
+
+    for file in data_files(data_path)? {
+        let name = file.file_name().unwrap().to_string_lossy().to_string();
+        for (i, line) in fs::read_to_string(&file)?.lines().enumerate() {
+            let location = Location::new(file.clone(), i + 1);
+            match parse_line(line) {
+                Ok(entry) => {
+                    if entry.data_file_name() != name {
+                        findings.push(Finding {
+                            location: location.clone(),
+                            problem: Problem::Misfiled(entry.data_file_name()),
+                        });
+                    }
+                    entries.push((location, entry));
+                }
+                Err(_) => findings.push(Finding {
+                    location,
+                    problem: if is_empty_range(line) {
+                        Problem::EmptyRange
+                    } else {
+                        Problem::Unparseable
+                    },
+                }),
+            }
+        }
+    }
+
+    entries.sort_by_key(|(_, e)| e.range().from());
+
+    let open: Vec<&Location> = entries
+        .iter()
+        .filter(|(_, e)| e.range().is_open())
+        .map(|(l, _)| l)
+        .collect();
+    for location in open.iter().rev().skip(1) {
+        findings.push(Finding {
+            location: (*location).clone(),
+            problem: Problem::ExtraOpen,
+        });
+    }
+
+    // The entry reaching the furthest so far: each entry starting before its end overlaps it.
+    // Open entries reach the end of time.
+    let mut furthest: Option<(DateTime<Utc>, &Location)> = None;
+    for (location, entry) in &entries {
+        let end = entry.range().to().unwrap_or(DateTime::<Utc>::MAX_UTC);
+        if let Some((f_end, f_location)) = furthest {
+            if entry.range().from() < f_end {
+                findings.push(Finding {
+                    location: location.clone(),
+                    problem: Problem::Overlap(f_location.clone()),
+                });
+            }
+            if end <= f_end {
+                continue;
+            }
+        }
+        furthest = Some((end, location));
+    }
+
+    findings.sort_by(|a, b| a.location.cmp(&b.location));
+
+    Ok(findings)
+}
+
+/// How `repair` resolves overlapping entries.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OverlapStrategy {
+    /// The earlier entry is cut where the later one starts. It is removed if nothing is left.
+    TrimEarlier,
+    /// The later entry is moved to start where the earlier one ends. It is removed if nothing is
+    /// left.
+    TrimLater,
+    /// Both entries are kept, and the overlap is reported.
+    Keep,
+}
+
+/// The changes made to a file of the database.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FileDiff {
+    file: PathBuf,
+    removed: Vec<String>,
+    added: Vec<String>,
+}
+
+impl FileDiff {
+    /// Return the path of the changed file.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Return the lines removed from the file.
+    pub fn removed(&self) -> &[String] {
+        &self.removed
+    }
+
+    /// Return the lines added to the file.
+    pub fn added(&self) -> &[String] {
+        &self.added
+    }
+}
+
+impl Display for FileDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "--- {}", self.file.display())?;
+        writeln!(f, "+++ {}", self.file.display())?;
+        for line in &self.removed {
+            writeln!(f, "-{}", line)?;
+        }
+        for line in &self.added {
+            writeln!(f, "+{}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The result of `repair`: the changes to the database files, the entries deleted and the
+/// problems left.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Repair {
+    diff: Vec<FileDiff>,
+    removed: Vec<TimeEntry>,
+    findings: Vec<Finding>,
+}
+
+impl Repair {
+    /// Return the changes made to each file, or that would be made in a dry run.
+    pub fn diff(&self) -> &[FileDiff] {
+        &self.diff
+    }
+
+    /// Return the entries that were deleted, or that would be deleted in a dry run, because
+    /// nothing was left of them once trimmed or closed. They are given as they were loaded, with
+    /// their location.
+    pub fn removed(&self) -> &[TimeEntry] {
+        &self.removed
+    }
+
+    /// Return the problems that were not repaired: the overlaps kept with
+    /// `OverlapStrategy::Keep`.
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+}
+
+// Return the lines of `a` that are not in `b`.
+fn missing_lines(a: &str, b: &str) -> Vec<String> {
+    let mut b: Vec<&str> = b.lines().collect();
+    a.lines()
+        .filter(|l| match b.iter().position(|m| m == l) {
+            Some(p) => {
+                b.remove(p);
+                false
+            }
+            None => true,
+        })
+        .map(String::from)
+        .collect()
+}
+
+fn with_range(entry: &TimeEntry, range: Range) -> TimeEntry {
+    TimeEntry::new(
+        range,
+        entry.tags().to_vec(),
+        entry.annotation().map(String::from),
+    )
+}
+
+/// Repair the timewarrior database at data_path:
+///  - Entries in the wrong data file are moved to the data file of the month they start in.
+///  - Open entries followed by other entries are closed when the next entry starts.
+///  - Overlapping entries are resolved with the given strategy.
+///
+/// Entries that would last less than a second once trimmed or closed are deleted, and returned by
+/// `Repair::removed`. Lines that cannot be parsed and empty ranges must be fixed by hand first.
+/// With `dry_run`, the database is left untouched and the returned diff shows the changes that
+/// would be made.
+pub fn repair(data_path: &Path, strategy: OverlapStrategy, dry_run: bool) -> Result<Repair> {
+    if let Some(f) = validate(data_path)?
+        .iter()
+        .find(|f| matches!(f.problem, Problem::Unparseable | Problem::EmptyRange))
+    {
+        bail!("{}: cannot be repaired automatically", f);
+    }
+
+    let mut work = Work::load_all(data_path)?;
+    let original: Vec<TimeEntry> = work.entries().iter().rev().cloned().collect();
+    // The repaired entries, from the oldest one. Removed entries are set to None.
+    let mut entries: Vec<Option<TimeEntry>> = original.iter().cloned().map(Some).collect();
+    // Misfiled entries are changed, so that they are stored in the right data file.
+    let mut changed: Vec<bool> = original
+        .iter()
+        .map(|e| {
+            e.location()
+                .is_some_and(|l| l.file().file_name() != Some(OsStr::new(&e.data_file_name())))
+        })
+        .collect();
+
+    for i in 0..original.len() {
+        if original[i].range().is_open() && i + 1 < original.len() {
+            let closed = Range::new(
+                original[i].range().from(),
+                Some(original[i + 1].range().from()),
+            );
+            entries[i] = closed.ok().map(|r| with_range(&original[i], r));
+            changed[i] = true;
+        }
+    }
+
+    let end = |e: &TimeEntry| e.range().to().unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let mut findings = vec![];
+    // The kept entry that ends the latest so far
+    let mut furthest: Option<usize> = None;
+    for i in 0..entries.len() {
+        let current = match &entries[i] {
+            Some(e) => e.clone(),
+            None => continue,
+        };
+        let last = match furthest {
+            Some(f) => f,
+            None => {
+                furthest = Some(i);
+                continue;
+            }
+        };
+        let previous = entries[last].clone().unwrap();
+
+        if current.range().from() < end(&previous) {
+            match strategy {
+                OverlapStrategy::TrimEarlier => {
+                    let trimmed = Range::new(previous.range().from(), Some(current.range().from()));
+                    entries[last] = trimmed.ok().map(|r| with_range(&previous, r));
+                    changed[last] = true;
+                }
+                OverlapStrategy::TrimLater => {
+                    let trimmed = Range::new(end(&previous), current.range().to());
+                    entries[i] = trimmed
+                        .ok()
+                        .filter(|_| end(&current) > end(&previous))
+                        .map(|r| with_range(&current, r));
+                    changed[i] = true;
+                }
+                OverlapStrategy::Keep => findings.push(Finding {
+                    location: original[i].location().unwrap().clone(),
+                    problem: Problem::Overlap(original[last].location().unwrap().clone()),
+                }),
+            }
+        }
+
+        let further = match (&entries[i], &entries[last]) {
+            (Some(c), Some(p)) => end(c) > end(p),
+            (Some(_), None) => true,
+            _ => false,
+        };
+        if further {
+            furthest = Some(i);
+        }
+    }
+
+    let removed = original
+        .iter()
+        .zip(&entries)
+        .filter(|(_, e)| e.is_none())
+        .map(|(o, _)| o.clone())
+        .collect();
+
+    // Removing the entries from the highest ID keeps the lower IDs valid.
+    for i in (0..original.len()).filter(|i| changed[*i]) {
+        work.remove(original[i].id())?;
+    }
+    for entry in entries
+        .into_iter()
+        .zip(changed)
+        .filter_map(|(e, c)| e.filter(|_| c))
+    {
+        work.insert(entry);
+    }
+
+    let mut diff = vec![];
+    // The undo journal is written too, but it is not part of the data.
+    let undo = data_path.join("undo.data");
+    for (file, data) in work.changed_files(data_path)? {
+        if file == undo {
+            continue;
+        }
+        let previous = fs::read_to_string(&file).unwrap_or_default();
+        diff.push(FileDiff {
+            removed: missing_lines(&previous, &data),
+            added: missing_lines(&data, &previous),
+            file,
+        });
+    }
+
+    if !dry_run {
+        work.store(data_path)?;
+    }
+
+    Ok(Repair {
+        diff,
+        removed,
+        findings,
+    })
+}
+
+#[cfg(test)]
+mod validation_tests {
+    use crate::data::{Location, TestDir};
+    use crate::validation::{repair, validate, OverlapStrategy, Problem};
+    use std::fs;
+
+    #[test]
+    fn test_validate() {
+        let path = TestDir::new("validate");
+        fs::write(
+            path.join("2022-07.data"),
+            "inc 20220711T080000Z - 20220711T090000Z # a\n\
+             inc 20220711T083000Z - 20220711T084500Z # b\n\
+             inc 20220711T100000Z - 20220711T100000Z # c\n\
+             inc 20220711T110000Z - 20220711T100000Z # d\n\
+             inc 20220712T110000Z # e\n\
+             inc 20220715T120000Z - 20220715T130000Z # \"f\n\
+             not an entry\n\
+             inc 20220815T120000Z - 20220815T130000Z # g\n",
+        )
+        .unwrap();
+        fs::write(
+            path.join("2022-08.data"),
+            "inc 20220814T120000Z - 20220814T130000Z # h\n\
+             inc 20220816T120000Z # i\n",
+        )
+        .unwrap();
+        fs::write(path.join("tags.data"), "{}").unwrap();
+
+        let july = |line| Location::new(path.join("2022-07.data"), line);
+        let august = |line| Location::new(path.join("2022-08.data"), line);
+        let findings: Vec<(Location, Problem)> = validate(&path)
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.location().clone(), f.problem().clone()))
+            .collect();
+
+        assert_eq!(
+            findings,
+            vec![
+                (july(2), Problem::Overlap(july(1))),
+                (july(3), Problem::EmptyRange),
+                (july(4), Problem::EmptyRange),
+                (july(5), Problem::ExtraOpen),
+                (july(6), Problem::Unparseable),
+                (july(7), Problem::Unparseable),
+                (july(8), Problem::Misfiled("2022-08.data".to_string())),
+                // The open entry of July overlaps all the entries after it
+                (july(8), Problem::Overlap(july(5))),
+                (august(1), Problem::Overlap(july(5))),
+                (august(2), Problem::Overlap(july(5))),
+            ]
+        );
+    }
+
+    // Repair the data and return the findings, the removed entries and the repaired data.
+    fn repair_data(
+        name: &str,
+        data: &str,
+        strategy: OverlapStrategy,
+    ) -> (Vec<String>, Vec<String>, String) {
+        let path = TestDir::new(&format!("repair-{}", name));
+        fs::write(path.join("2022-07.data"), data).unwrap();
+
+        // A dry run changes nothing
+        let dry = repair(&path, strategy, true).unwrap();
+        assert_eq!(fs::read_to_string(path.join("2022-07.data")).unwrap(), data);
+
+        let repaired = repair(&path, strategy, false).unwrap();
+        assert_eq!(dry, repaired);
+        let findings = repaired.findings().iter().map(|f| f.to_string()).collect();
+        let removed = repaired.removed().iter().map(|e| e.serialize()).collect();
+        let data = fs::read_to_string(path.join("2022-07.data")).unwrap();
+        (findings, removed, data)
+    }
+
+    #[test]
+    fn test_repair() {
+        let data = "inc 20220711T080000Z - 20220711T100000Z # a\n\
+                    inc 20220711T090000Z - 20220711T093000Z # b\n\
+                    inc 20220711T092000Z - 20220711T110000Z # c\n\
+                    inc 20220712T080000Z # d\n\
+                    inc 20220712T090000Z - 20220712T100000Z # e\n\
+                    inc 20220712T110000Z # f\n";
+
+        let (findings, removed, repaired) =
+            repair_data("earlier", data, OverlapStrategy::TrimEarlier);
+        assert!(findings.is_empty());
+        assert!(removed.is_empty());
+        assert_eq!(
+            repaired,
+            "inc 20220711T080000Z - 20220711T090000Z # a\n\
+             inc 20220711T090000Z - 20220711T092000Z # b\n\
+             inc 20220711T092000Z - 20220711T110000Z # c\n\
+             inc 20220712T080000Z - 20220712T090000Z # d\n\
+             inc 20220712T090000Z - 20220712T100000Z # e\n\
+             inc 20220712T110000Z # f\n"
+        );
+
+        let (findings, removed, repaired) = repair_data("later", data, OverlapStrategy::TrimLater);
+        assert!(findings.is_empty());
+        assert_eq!(removed, ["inc 20220711T090000Z - 20220711T093000Z # b"]);
+        assert_eq!(
+            repaired,
+            "inc 20220711T080000Z - 20220711T100000Z # a\n\
+             inc 20220711T100000Z - 20220711T110000Z # c\n\
+             inc 20220712T080000Z - 20220712T090000Z # d\n\
+             inc 20220712T090000Z - 20220712T100000Z # e\n\
+             inc 20220712T110000Z # f\n"
+        );
+
+        let (findings, removed, repaired) = repair_data("keep", data, OverlapStrategy::Keep);
+        assert_eq!(findings.len(), 2);
+        assert!(removed.is_empty());
+        assert!(findings[0].contains("2022-07.data:2: the entry overlaps "));
+        assert!(findings[0].ends_with("2022-07.data:1"));
+        assert!(findings[1].contains("2022-07.data:3: the entry overlaps "));
+        assert!(findings[1].ends_with("2022-07.data:1"));
+        assert_eq!(
+            repaired,
+            data.replace(
+                "20220712T080000Z #",
+                "20220712T080000Z - 20220712T090000Z #"
+            )
+        );
+    }
+
+    #[test]
+    fn test_repair_removed() {
+        // Nothing is left of the entry cut where the open entry starts
+        let data = "inc 20220711T080000Z # a\n\
+                    inc 20220711T080000Z - 20220711T090000Z # b\n";
+
+        let (_, removed, repaired) = repair_data("removed", data, OverlapStrategy::TrimEarlier);
+        assert_eq!(removed, ["inc 20220711T080000Z - 20220711T090000Z # b"]);
+        assert_eq!(repaired, "inc 20220711T080000Z # a\n");
+    }
+
+    #[test]
+    fn test_repair_misfiled() {
+        let path = TestDir::new("misfiled");
+        fs::write(
+            path.join("2022-07.data"),
+            "inc 20220711T080000Z - 20220711T100000Z # a\n\
+             inc 20220731T230000Z - 20220801T010000Z # c\n\
+             inc 20220815T080000Z - 20220815T100000Z # b\n",
+        )
+        .unwrap();
+
+        // Files are named by the month in UTC, whatever the local timezone
+        assert_eq!(validate(&path).unwrap().len(), 1);
+
+        let repaired = repair(&path, OverlapStrategy::Keep, false).unwrap();
+        assert_eq!(repaired.diff().len(), 2);
+        assert_eq!(
+            repaired.diff()[0].to_string(),
+            format!(
+                "--- {0}\n+++ {0}\n-inc 20220815T080000Z - 20220815T100000Z # b\n",
+                path.join("2022-07.data").display()
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(path.join("2022-08.data")).unwrap(),
+            "inc 20220815T080000Z - 20220815T100000Z # b\n"
+        );
+        assert!(validate(&path).unwrap().is_empty());
+
+        // Unparseable lines are not repaired
+        fs::write(path.join("2022-09.data"), "not an entry\n").unwrap();
+        assert!(repair(&path, OverlapStrategy::Keep, false).is_err());
+    }
+}